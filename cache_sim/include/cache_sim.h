@@ -0,0 +1,139 @@
+#ifndef CACHE_SIM_H
+#define CACHE_SIM_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdint.h>
+
+/**
+ * Outcome of a call.
+ */
+typedef enum CacheSimStatus {
+  CACHE_SIM_STATUS_OK = 0,
+  /**
+   * the access is not aligned to its own size
+   */
+  CACHE_SIM_STATUS_MISALIGNED = 1,
+  /**
+   * the access runs past the end of main memory
+   */
+  CACHE_SIM_STATUS_OUT_OF_BOUNDS = 2,
+  /**
+   * a NULL handle, a size other than 1/2/4/8, an unknown level name
+   */
+  CACHE_SIM_STATUS_INVALID_ARGUMENT = 3,
+  /**
+   * a bug in the simulator; the handle must not be used again
+   */
+  CACHE_SIM_STATUS_INTERNAL = 4,
+} CacheSimStatus;
+
+/**
+ * A simulated hierarchy; opaque to C.
+ */
+typedef struct CacheSim CacheSim;
+
+/**
+ * Counters of one level, as in the csv/json reports.
+ */
+typedef struct CacheSimStats {
+  uint64_t read_hits;
+  uint64_t read_misses;
+  uint64_t write_hits;
+  uint64_t write_misses;
+  uint64_t compulsory;
+  uint64_t capacity;
+  uint64_t conflict;
+  uint64_t writes;
+  uint64_t writebacks;
+  uint64_t bytes_written;
+  uint64_t fills;
+  uint64_t clean_evictions;
+  uint64_t dirty_writebacks;
+  uint64_t bytes_in;
+  uint64_t bytes_out;
+  uint64_t cycles;
+  /**
+   * average memory access time in cycles
+   */
+  double amat;
+} CacheSimStats;
+
+#ifdef __cplusplus
+extern "C" {
+#endif  // __cplusplus
+
+/**
+ * Create a hierarchy from a `.toml` or `.json` file, or the default one
+ * for a NULL path. Returns NULL on failure; cache_sim_last_error says why.
+ * policy = "opt" is refused: it needs the whole trace up front.
+ *
+ * # Safety
+ * `config_path` is NULL or a NUL-terminated string.
+ */
+struct CacheSim *cache_sim_new(const char *config_path);
+
+/**
+ * Destroy a handle from cache_sim_new. NULL is ignored.
+ *
+ * # Safety
+ * `sim` is NULL or a live handle, not used again afterwards.
+ */
+void cache_sim_free(struct CacheSim *sim);
+
+/**
+ * Load `size` (1, 2, 4 or 8) bytes at addr through the D-side. The value
+ * read is stored to `value` unless it is NULL.
+ *
+ * # Safety
+ * `sim` is a live handle; `value` is NULL or writable.
+ */
+enum CacheSimStatus cache_sim_read(struct CacheSim *sim, uint64_t addr, uint32_t size, uint64_t *value);
+
+/**
+ * Store the low `size` (1, 2, 4 or 8) bytes of value at addr.
+ *
+ * # Safety
+ * `sim` is a live handle.
+ */
+enum CacheSimStatus cache_sim_write(struct CacheSim *sim, uint64_t addr, uint32_t size, uint64_t value);
+
+/**
+ * Fetch `size` (1, 2, 4 or 8) bytes of instructions at addr.
+ *
+ * # Safety
+ * `sim` is a live handle.
+ */
+enum CacheSimStatus cache_sim_fetch(struct CacheSim *sim, uint64_t addr, uint32_t size);
+
+/**
+ * Retire whatever the write buffer still holds to main memory, as the
+ * binary does at the end of a trace.
+ *
+ * # Safety
+ * `sim` is a live handle.
+ */
+enum CacheSimStatus cache_sim_drain(struct CacheSim *sim);
+
+/**
+ * Counters of one level so far: "memory" for the whole hierarchy, "l1i",
+ * "l1d", "l2", "l3" or "main".
+ *
+ * # Safety
+ * `sim` is a live handle, `level` a NUL-terminated string and `out` writable.
+ */
+enum CacheSimStatus cache_sim_stats(struct CacheSim *sim,
+                                    const char *level,
+                                    struct CacheSimStats *out);
+
+/**
+ * Why the last call on this thread failed, or NULL if none has. Valid until
+ * the next failing call on the same thread.
+ */
+const char *cache_sim_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CACHE_SIM_H */
@@ -2,27 +2,259 @@ use crate::memory::*;
 use crate::mem_stats::*;
 use crate::direct_map::*;
 use crate::set_associative::*;
+use crate::fully_associative::*;
+use crate::opt::NextUse;
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::str::FromStr;
 
 const WORDSIZE: usize = DataTypeSize::get_size(DataTypeSize::Word);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum EvictionPolicy {
     Lru,
     Nru,
-    Random
+    Random,     // victim drawn at the previous fill, so every query before a fill agrees
+    Plru,       // tree pseudo-LRU
+    Srrip,      // static re-reference interval prediction
+    Brrip,      // bimodal RRIP
+    Drrip,      // SRRIP/BRRIP picked by set dueling
+    Opt,        // Belady MIN, needs the whole trace up front
+}
+
+impl EvictionPolicy {
+    pub fn is_rrip(self) -> bool {
+        matches!(self, EvictionPolicy::Srrip | EvictionPolicy::Brrip | EvictionPolicy::Drrip)
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru"    => Ok(EvictionPolicy::Lru),
+            "nru"    => Ok(EvictionPolicy::Nru),
+            "random" => Ok(EvictionPolicy::Random),
+            "plru"   => Ok(EvictionPolicy::Plru),
+            "srrip"  => Ok(EvictionPolicy::Srrip),
+            "brrip"  => Ok(EvictionPolicy::Brrip),
+            "drrip"  => Ok(EvictionPolicy::Drrip),
+            "opt"    => Ok(EvictionPolicy::Opt),
+            _ => Err(format!("unknown eviction policy '{s}' (expected lru, nru, random, plru, srrip, brrip, drrip or opt)")),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EvictionPolicy::Lru    => "lru",
+            EvictionPolicy::Nru    => "nru",
+            EvictionPolicy::Random => "random",
+            EvictionPolicy::Plru   => "plru",
+            EvictionPolicy::Srrip  => "srrip",
+            EvictionPolicy::Brrip  => "brrip",
+            EvictionPolicy::Drrip  => "drrip",
+            EvictionPolicy::Opt    => "opt",
+        };
+        f.write_str(name)
+    }
+}
+
+/* ── write policies ─────────────────────────────────────────────────── */
+
+/// What a write hit does beyond updating the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteHit {
+    /// mark the line dirty, write it back on eviction
+    #[default]
+    Back,
+    /// pass the write to the level below at once, the line stays clean
+    Through,
+}
+
+/// Whether a write miss brings the line in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteMiss {
+    /// fill the line, then write it as a hit
+    #[default]
+    Allocate,
+    /// leave this level alone and pass the write down (no-write-allocate)
+    Around,
+}
+
+impl fmt::Display for WriteHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WriteHit::Back    => "back",
+            WriteHit::Through => "through",
+        })
+    }
+}
+
+impl fmt::Display for WriteMiss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WriteMiss::Allocate => "allocate",
+            WriteMiss::Around   => "around",
+        })
+    }
+}
+
+impl FromStr for WriteHit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "back"    | "write-back"    => Ok(WriteHit::Back),
+            "through" | "write-through" => Ok(WriteHit::Through),
+            _ => Err(format!("unknown write-hit policy '{s}' (expected back or through)")),
+        }
+    }
+}
+
+impl FromStr for WriteMiss {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allocate" | "write-allocate" => Ok(WriteMiss::Allocate),
+            "around"   | "no-allocate"    => Ok(WriteMiss::Around),
+            _ => Err(format!("unknown write-miss policy '{s}' (expected allocate or around)")),
+        }
+    }
+}
+
+/* ── runtime cache geometry ──────────────────────────────────────────── */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    #[serde(deserialize_with = "crate::config::de_size")]
+    pub bytes: usize,           // total data capacity
+    pub words_per_line: usize,  // line size in words
+    #[serde(default = "CacheConfig::default_assoc")]
+    #[serde(deserialize_with = "crate::config::de_assoc", serialize_with = "crate::config::ser_assoc")]
+    pub assoc: usize,           // ways per set, 1 = direct mapped, 0 = fully associative
+    #[serde(default = "CacheConfig::default_policy")]
+    pub policy: EvictionPolicy, // ignored when direct mapped
+    #[serde(default)]
+    pub write_hit: WriteHit,
+    #[serde(default)]
+    pub write_miss: WriteMiss,
+    #[serde(default = "CacheConfig::default_hit_latency")]
+    pub hit_latency: u64,       // cycles to serve a hit
+    #[serde(default)]
+    pub miss_penalty: u64,      // cycles a miss adds on top of the level below
+}
+
+impl CacheConfig {
+    pub fn direct_mapped(bytes: usize, words_per_line: usize) -> Self {
+        Self::set_associative(bytes, words_per_line, 1, EvictionPolicy::Lru)
+    }
+
+    pub fn set_associative(bytes: usize, words_per_line: usize, assoc: usize, policy: EvictionPolicy) -> Self {
+        CacheConfig {
+            bytes, words_per_line, assoc, policy,
+            write_hit: WriteHit::default(), write_miss: WriteMiss::default(),
+            hit_latency: Self::default_hit_latency(), miss_penalty: 0,
+        }
+    }
+
+    pub fn fully_associative(bytes: usize, words_per_line: usize, policy: EvictionPolicy) -> Self {
+        Self::set_associative(bytes, words_per_line, Self::FULLY_ASSOCIATIVE, policy)
+    }
+
+    pub fn with_writes(self, write_hit: WriteHit, write_miss: WriteMiss) -> Self {
+        CacheConfig { write_hit, write_miss, ..self }
+    }
+
+    pub fn with_latency(self, hit_latency: u64, miss_penalty: u64) -> Self {
+        CacheConfig { hit_latency, miss_penalty, ..self }
+    }
+
+    /// `assoc` value asking for a single set holding every line.
+    pub const FULLY_ASSOCIATIVE: usize = 0;
+
+    pub fn is_fully_associative(&self) -> bool { self.assoc == Self::FULLY_ASSOCIATIVE }
+
+    /// Lines per set, with the fully-associative marker resolved.
+    pub fn ways(&self) -> usize {
+        if self.is_fully_associative() { self.num_lines() } else { self.assoc }
+    }
+
+    fn default_assoc() -> usize { 1 }
+    fn default_policy() -> EvictionPolicy { EvictionPolicy::Lru }
+    fn default_hit_latency() -> u64 { 1 }
+
+    pub fn line_bytes(&self) -> usize { self.words_per_line * WORDSIZE }
+    pub fn num_lines(&self) -> usize  { self.bytes / self.line_bytes() }
+    pub fn num_sets(&self) -> usize   { self.num_lines() / self.ways() }
 }
 
 #[derive(Debug)]
-pub enum Cache<
-    const BYTES: usize,
-    const WORDS_PER_LINE: usize,
-    const ASSOC: usize = 1,        // only SA/FA care
-> {
-    DirectMapped(DMCache<BYTES, WORDS_PER_LINE>),
-    SetAssociative(SetAssocCache<BYTES, WORDS_PER_LINE, ASSOC>),
-    // FullyAssociative(FAssocCache<BYTES, WORDS_PER_LINE>),
+pub enum Cache {
+    DirectMapped(DMCache),
+    SetAssociative(SetAssocCache),
+    FullyAssociative(FAssocCache),
+}
+
+impl Cache {
+    /// `seed` feeds the RNG behind `EvictionPolicy::Random`; other policies ignore it.
+    pub fn new(config: &CacheConfig, seed: u64) -> Self {
+        match config.assoc {
+            CacheConfig::FULLY_ASSOCIATIVE => Cache::FullyAssociative(FAssocCache::new(
+                config.bytes, config.words_per_line, config.policy,
+            ).with_seed(seed)),
+            1 => Cache::DirectMapped(DMCache::new(config.bytes, config.words_per_line)),
+            _ => Cache::SetAssociative(SetAssocCache::new(
+                config.bytes, config.words_per_line, config.assoc, config.policy,
+            ).with_seed(seed)),
+        }
+    }
+
+    /// Whether this cache evicts with Belady OPT and so needs `set_oracle`.
+    pub fn uses_opt(&self) -> bool {
+        match self {
+            Cache::DirectMapped(_) => false,
+            Cache::SetAssociative(sa) => sa.eviction() == EvictionPolicy::Opt,
+            Cache::FullyAssociative(fa) => fa.eviction() == EvictionPolicy::Opt,
+        }
+    }
+
+    /// Clear the dirty bit of addr's line, if present: under write-through
+    /// the level below already has the data.
+    pub fn clean_line(&mut self, addr: usize) {
+        let line = match self {
+            Cache::DirectMapped(dm) => dm.line_mut(addr),
+            Cache::SetAssociative(sa) => sa.line_mut(addr),
+            Cache::FullyAssociative(fa) => fa.line_mut(addr),
+        };
+        if let Some(line) = line { line.clean(); }
+    }
+
+    /// Time spent serving one access here, levels below included.
+    pub fn record_cycles(&mut self, cycles: u64) {
+        let stats = match self {
+            Cache::DirectMapped(dm) => &mut dm.stats,
+            Cache::SetAssociative(sa) => &mut sa.stats,
+            Cache::FullyAssociative(fa) => &mut fa.stats,
+        };
+        stats.record_cycles(cycles);
+    }
+
+    pub fn set_oracle(&mut self, oracle: NextUse) {
+        match self {
+            Cache::DirectMapped(_) => {}
+            Cache::SetAssociative(sa) => sa.set_oracle(oracle),
+            Cache::FullyAssociative(fa) => fa.set_oracle(oracle),
+        }
+    }
 }
 
 pub trait CacheAddressing {
@@ -40,22 +272,23 @@ pub trait CacheAddressing {
     fn byte_bits(&self) -> usize;
     fn word_bits(&self) -> usize;
     fn index_bits(&self) -> usize;
+    fn words_per_line(&self) -> usize;
 }
 
-impl<const B: usize, const W: usize, const A: usize> MemoryAccess for Cache<B, W, A> {
-    fn read(&mut self, addr: usize, size: DataTypeSize, dont_count: bool) -> Result<DataType, MemoryError> {
+impl CacheAccess for Cache {
+    fn read(&mut self, addr: usize, size: DataTypeSize, dont_count: bool) -> Lookup<DataType> {
         match self {
             Cache::DirectMapped(dm) => dm.read(addr, size, dont_count),
             Cache::SetAssociative(sa) => sa.read(addr, size, dont_count),
-            // Cache::FullyAssociative(fa) => fa.read(addr, size),
+            Cache::FullyAssociative(fa) => fa.read(addr, size, dont_count),
         }
     }
 
-    fn write(&mut self, data: DataType, addr: usize, dont_count: bool) -> Result<(), MemoryError> {
+    fn write(&mut self, data: DataType, addr: usize, dont_count: bool) -> Lookup<()> {
         match self {
             Cache::DirectMapped(dm) => dm.write(data, addr, dont_count),
             Cache::SetAssociative(sa) => sa.write(data, addr, dont_count),
-            // Cache::FullyAssociative(fa) => fa.write(data, addr),
+            Cache::FullyAssociative(fa) => fa.write(data, addr, dont_count),
         }
     }
 
@@ -63,17 +296,17 @@ impl<const B: usize, const W: usize, const A: usize> MemoryAccess for Cache<B, W
         match self {
             Cache::DirectMapped(dm) => dm.stats(),
             Cache::SetAssociative(sa) => sa.stats(),
-            // Cache::FullyAssociative(fa) => fa.stats(),
+            Cache::FullyAssociative(fa) => fa.stats(),
         }
     }
 }
 
-impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B, W, A> {
+impl CacheAddressing for Cache {
     fn get_tag(&self, addr: usize) -> usize {
         match self {
             Cache::DirectMapped(dm) => dm.get_tag(addr),
             Cache::SetAssociative(sa)    => sa.get_tag(addr),
-            // Cache::FullyAssociative(fa)  => fa.get_tag(addr),
+            Cache::FullyAssociative(fa)  => fa.get_tag(addr),
         }
     }
 
@@ -81,7 +314,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.get_index(addr),
             Cache::SetAssociative(sa)    => sa.get_index(addr),
-            // Cache::FullyAssociative(fa)  => fa.get_index(addr),
+            Cache::FullyAssociative(fa)  => fa.get_index(addr),
         }
     }
 
@@ -89,7 +322,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.get_word_offset(addr),
             Cache::SetAssociative(sa)    => sa.get_word_offset(addr),
-            // Cache::FullyAssociative(fa)  => fa.get_word_offset(addr),
+            Cache::FullyAssociative(fa)  => fa.get_word_offset(addr),
         }
     }
 
@@ -97,7 +330,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.get_byte_offset(addr),
             Cache::SetAssociative(sa)    => sa.get_byte_offset(addr),
-            // Cache::FullyAssociative(fa)  => fa.get_byte_offset(addr),
+            Cache::FullyAssociative(fa)  => fa.get_byte_offset(addr),
         }
     }
 
@@ -105,7 +338,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.is_line_dirty(addr),
             Cache::SetAssociative(sa)    => sa.is_line_dirty(addr),
-            // Cache::FullyAssociative(fa)  => fa.is_line_dirty(addr),
+            Cache::FullyAssociative(fa)  => fa.is_line_dirty(addr),
         }
     }
 
@@ -113,7 +346,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.get_evict_line_data(addr),
             Cache::SetAssociative(sa)    => sa.get_evict_line_data(addr),
-            // Cache::FullyAssociative(fa)  => fa.get_evict_line_data(addr),
+            Cache::FullyAssociative(fa)  => fa.get_evict_line_data(addr),
         }
     }
 
@@ -121,7 +354,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.get_writeback_addr(addr),
             Cache::SetAssociative(sa)    => sa.get_writeback_addr(addr),
-            // Cache::FullyAssociative(fa)  => fa.get_writeback_addr(addr),
+            Cache::FullyAssociative(fa)  => fa.get_writeback_addr(addr),
         } 
     }
 
@@ -129,7 +362,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.get_base_addr(addr),
             Cache::SetAssociative(sa)    => sa.get_base_addr(addr),
-            // Cache::FullyAssociative(fa)  => fa.get_base_addr(addr),
+            Cache::FullyAssociative(fa)  => fa.get_base_addr(addr),
         } 
     }
 
@@ -137,7 +370,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.decode_addr(addr),
             Cache::SetAssociative(sa)    => sa.decode_addr(addr),
-            // Cache::FullyAssociative(fa)  => fa.decode_addr(addr),
+            Cache::FullyAssociative(fa)  => fa.decode_addr(addr),
         }
     }
 
@@ -145,7 +378,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.index_bits(),
             Cache::SetAssociative(sa)    => sa.index_bits(),
-            // Cache::FullyAssociative(fa)  => fa.index_bits(),
+            Cache::FullyAssociative(fa)  => fa.index_bits(),
         }
     }
 
@@ -153,7 +386,7 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.word_bits(),
             Cache::SetAssociative(sa)    => sa.word_bits(),
-            // Cache::FullyAssociative(fa)  => fa.word_bits(),
+            Cache::FullyAssociative(fa)  => fa.word_bits(),
         } 
     }
 
@@ -161,25 +394,41 @@ impl<const B: usize, const W: usize, const A: usize> CacheAddressing for Cache<B
         match self {
             Cache::DirectMapped(dm) => dm.byte_bits(),
             Cache::SetAssociative(sa)    => sa.byte_bits(),
-            // Cache::FullyAssociative(fa)  => fa.byte_bits(),
+            Cache::FullyAssociative(fa)  => fa.byte_bits(),
         } 
     }
+
+    fn words_per_line(&self) -> usize {
+        match self {
+            Cache::DirectMapped(dm) => dm.words_per_line(),
+            Cache::SetAssociative(sa)    => sa.words_per_line(),
+            Cache::FullyAssociative(fa)  => fa.words_per_line(),
+        }
+    }
 }
 
-impl<const B: usize, const W: usize, const A: usize> MemLevelAccess for Cache<B, W, A> {
+impl MemLevelAccess for Cache {
     fn write_line(&mut self, addr: usize, words_per_lines: usize, data: Vec<u8>) {
         match self {
             Cache::DirectMapped(dm) => dm.write_line(addr, words_per_lines, data),
             Cache::SetAssociative(sa) => sa.write_line(addr, words_per_lines, data),
-            // Cache::FullyAssociative(fa) => fa.write_line(addr, words_per_lines),
+            Cache::FullyAssociative(fa) => fa.write_line(addr, words_per_lines, data),
+        }
+    }
+
+    fn fetch_line(&mut self, addr: usize, words_per_lines: usize, dont_count: bool) -> Lookup<Vec<u8>> {
+        match self {
+            Cache::DirectMapped(dm) => dm.fetch_line(addr, words_per_lines, dont_count),
+            Cache::SetAssociative(sa) => sa.fetch_line(addr, words_per_lines, dont_count),
+            Cache::FullyAssociative(fa) => fa.fetch_line(addr, words_per_lines, dont_count),
         }
     }
 
-    fn fetch_line(&self, addr: usize, words_per_lines: usize) -> Vec<u8> {
+    fn writeback_line(&mut self, addr: usize, words_per_lines: usize, data: Vec<u8>, dont_count: bool) -> Lookup<()> {
         match self {
-            Cache::DirectMapped(dm) => dm.fetch_line(addr, words_per_lines),
-            Cache::SetAssociative(sa) => sa.fetch_line(addr, words_per_lines),
-            // Cache::FullyAssociative(fa) => fa.fetch_line(addr, size),
+            Cache::DirectMapped(dm) => dm.writeback_line(addr, words_per_lines, data, dont_count),
+            Cache::SetAssociative(sa) => sa.writeback_line(addr, words_per_lines, data, dont_count),
+            Cache::FullyAssociative(fa) => fa.writeback_line(addr, words_per_lines, data, dont_count),
         }
     }
 }
@@ -189,7 +438,7 @@ pub struct CacheLine {
     valid: bool,
     dirty: bool,
     tag: usize,
-    time: u128,
+    time: u64,      // owning cache's clock at the last reference, 0 = never
     data: Vec<u8>,
 }
 
@@ -208,14 +457,11 @@ impl CacheLine {
     pub fn is_valid(&self) -> bool { self.valid }
     pub fn is_dirty(&self) -> bool { self.dirty }
     pub fn tag(&self) -> usize     { self.tag }
-    pub fn time(&self) -> u128     { self.time }
+    pub fn time(&self) -> u64      { self.time }
     pub fn get_data(&self) -> Vec<u8> { self.data.clone()}
 
-    pub fn stamp_now(&mut self) {
-        self.time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("clock before 1970")
-            .as_nanos();
+    pub fn stamp(&mut self, now: u64) {
+        self.time = now;
     }
 
     pub fn read_byte(&self, offset: usize) -> u8 {
@@ -227,20 +473,31 @@ impl CacheLine {
         self.dirty = true;
     }
 
+    pub fn clean(&mut self) {
+        self.dirty = false;
+    }
+
     pub fn write_line(&mut self, tag: usize, new_data: Vec<u8>) {
         self.tag = tag;
         self.data = new_data.clone();
         self.valid = true;
         self.dirty = false;
-        self.stamp_now();
+        self.time = 0;      // the filling cache stamps it if its policy cares
     }
 
     pub fn read_line_data(&self) -> Vec<u8> {
         self.data.clone()
     }
 
-    pub fn reset_time(&mut self) {
-        self.time = 0;
+    /* part of the line handed to (or written back from) a level with shorter lines */
+    pub fn read_bytes(&self, offset: usize, len: usize) -> Vec<u8> {
+        self.data[offset..offset + len].to_vec()
     }
 
+    pub fn write_bytes(&mut self, offset: usize, bytes: &[u8]) {
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.dirty = true;
+    }
+
+
 }
\ No newline at end of file
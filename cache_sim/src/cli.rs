@@ -0,0 +1,411 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use cache_sim::cache::{CacheConfig, EvictionPolicy, WriteHit, WriteMiss};
+use cache_sim::config::{parse_addr, parse_assoc, parse_size, ConfigError};
+use cache_sim::memory::{MemoryConfig, Routing};
+use cache_sim::trace::TraceFormat;
+use cache_sim::write_buffer::{DrainPolicy, WriteBufferConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// indented summary per level
+    Text,
+    /// one row per level with its counters and geometry, with a header
+    Csv,
+    /// the configuration plus every level's counters as one JSON object
+    Json,
+}
+
+/// Trace-driven simulator for split I/D L1 caches, an optional unified L2
+/// and L3, and main memory.
+///
+/// Every geometry flag is optional; anything left out keeps the value from
+/// `--config`, or the built-in default hierarchy when no file is given.
+#[derive(Debug, Parser)]
+#[command(name = "cache_sim", version)]
+pub struct Args {
+    /// Memory trace, one `r|w|i <hex addr> <size> [value]` record per line
+    /// or a Dinero `.din` or Valgrind Lackey trace; `-` reads stdin, and
+    /// gzip or zstd input is decompressed on the fly
+    pub trace: PathBuf,
+
+    /// How the trace is encoded (auto, text, dinero, lackey, binary); auto goes by
+    /// the extension and the first record
+    #[arg(long, value_name = "FORMAT", default_value_t = TraceFormat::Auto)]
+    pub trace_format: TraceFormat,
+
+    /// Stop at the first bad or misaligned record; by default such records
+    /// are skipped and counted
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Rewrite the trace in the compact binary format to FILE and exit
+    /// without simulating; later runs read FILE much faster
+    #[arg(long, value_name = "FILE")]
+    pub convert: Option<PathBuf>,
+
+    /// Hierarchy description (.toml or .json) used as the base configuration
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// How an access picks its L1: by its kind (fetches to the I-cache), or
+    /// by address against dm_base for traces without fetches
+    #[arg(long, value_name = "ROUTING")]
+    pub routing: Option<Routing>,
+
+    /// Start of the data region; lower addresses go to the I-cache under
+    /// `--routing address`
+    #[arg(long, value_parser = parse_addr, value_name = "ADDR")]
+    pub dm_base: Option<usize>,
+
+    /// Start of the MMIO region; accesses at or above it bypass the caches
+    #[arg(long, value_parser = parse_addr, value_name = "ADDR")]
+    pub mmio_base: Option<usize>,
+
+    /// Main memory size in bytes (accepts K/M/G suffixes)
+    #[arg(long, value_parser = parse_size, value_name = "BYTES")]
+    pub mem_size: Option<usize>,
+
+    /// I-L1 capacity in bytes (accepts K/M/G suffixes)
+    #[arg(long, value_parser = parse_size, value_name = "BYTES")]
+    pub l1i_size: Option<usize>,
+
+    /// I-L1 line size in 32-bit words
+    #[arg(long, value_name = "WORDS")]
+    pub l1i_words_per_line: Option<usize>,
+
+    /// I-L1 associativity, 1 = direct mapped, `full` = fully associative
+    #[arg(long, value_parser = parse_assoc, value_name = "WAYS")]
+    pub l1i_assoc: Option<usize>,
+
+    /// I-L1 eviction policy (lru, nru, random, plru, srrip, brrip, drrip, opt)
+    #[arg(long, value_name = "POLICY")]
+    pub l1i_policy: Option<EvictionPolicy>,
+
+    /// I-L1 write-hit policy (back, through)
+    #[arg(long, value_name = "POLICY")]
+    pub l1i_write_hit: Option<WriteHit>,
+
+    /// I-L1 write-miss policy (allocate, around)
+    #[arg(long, value_name = "POLICY")]
+    pub l1i_write_miss: Option<WriteMiss>,
+
+    /// I-L1 hit latency in cycles
+    #[arg(long, value_name = "CYCLES")]
+    pub l1i_hit_latency: Option<u64>,
+
+    /// I-L1 miss penalty: cycles a miss adds on top of the level below
+    #[arg(long, value_name = "CYCLES")]
+    pub l1i_miss_penalty: Option<u64>,
+
+    /// D-L1 capacity in bytes (accepts K/M/G suffixes)
+    #[arg(long, value_parser = parse_size, value_name = "BYTES")]
+    pub l1d_size: Option<usize>,
+
+    /// D-L1 line size in 32-bit words
+    #[arg(long, value_name = "WORDS")]
+    pub l1d_words_per_line: Option<usize>,
+
+    /// D-L1 associativity, 1 = direct mapped, `full` = fully associative
+    #[arg(long, value_parser = parse_assoc, value_name = "WAYS")]
+    pub l1d_assoc: Option<usize>,
+
+    /// D-L1 eviction policy (lru, nru, random, plru, srrip, brrip, drrip, opt)
+    #[arg(long, value_name = "POLICY")]
+    pub l1d_policy: Option<EvictionPolicy>,
+
+    /// D-L1 write-hit policy (back, through)
+    #[arg(long, value_name = "POLICY")]
+    pub l1d_write_hit: Option<WriteHit>,
+
+    /// D-L1 write-miss policy (allocate, around)
+    #[arg(long, value_name = "POLICY")]
+    pub l1d_write_miss: Option<WriteMiss>,
+
+    /// D-L1 hit latency in cycles
+    #[arg(long, value_name = "CYCLES")]
+    pub l1d_hit_latency: Option<u64>,
+
+    /// D-L1 miss penalty: cycles a miss adds on top of the level below
+    #[arg(long, value_name = "CYCLES")]
+    pub l1d_miss_penalty: Option<u64>,
+
+    /// Unified L2 capacity in bytes; adds an L2 when the config has none
+    #[arg(long, value_parser = parse_size, value_name = "BYTES")]
+    pub l2_size: Option<usize>,
+
+    /// L2 line size in 32-bit words
+    #[arg(long, value_name = "WORDS")]
+    pub l2_words_per_line: Option<usize>,
+
+    /// L2 associativity, 1 = direct mapped, `full` = fully associative
+    #[arg(long, value_parser = parse_assoc, value_name = "WAYS")]
+    pub l2_assoc: Option<usize>,
+
+    /// L2 eviction policy (lru, nru, random, plru, srrip, brrip, drrip, opt)
+    #[arg(long, value_name = "POLICY")]
+    pub l2_policy: Option<EvictionPolicy>,
+
+    /// L2 write-hit policy (back, through)
+    #[arg(long, value_name = "POLICY")]
+    pub l2_write_hit: Option<WriteHit>,
+
+    /// L2 write-miss policy (allocate, around)
+    #[arg(long, value_name = "POLICY")]
+    pub l2_write_miss: Option<WriteMiss>,
+
+    /// L2 hit latency in cycles
+    #[arg(long, value_name = "CYCLES")]
+    pub l2_hit_latency: Option<u64>,
+
+    /// L2 miss penalty: cycles a miss adds on top of the level below
+    #[arg(long, value_name = "CYCLES")]
+    pub l2_miss_penalty: Option<u64>,
+
+    /// Unified L3 capacity in bytes; adds an L3 when the config has none
+    #[arg(long, value_parser = parse_size, value_name = "BYTES")]
+    pub l3_size: Option<usize>,
+
+    /// L3 line size in 32-bit words
+    #[arg(long, value_name = "WORDS")]
+    pub l3_words_per_line: Option<usize>,
+
+    /// L3 associativity, 1 = direct mapped, `full` = fully associative
+    #[arg(long, value_parser = parse_assoc, value_name = "WAYS")]
+    pub l3_assoc: Option<usize>,
+
+    /// L3 eviction policy (lru, nru, random, plru, srrip, brrip, drrip, opt)
+    #[arg(long, value_name = "POLICY")]
+    pub l3_policy: Option<EvictionPolicy>,
+
+    /// L3 write-hit policy (back, through)
+    #[arg(long, value_name = "POLICY")]
+    pub l3_write_hit: Option<WriteHit>,
+
+    /// L3 write-miss policy (allocate, around)
+    #[arg(long, value_name = "POLICY")]
+    pub l3_write_miss: Option<WriteMiss>,
+
+    /// L3 hit latency in cycles
+    #[arg(long, value_name = "CYCLES")]
+    pub l3_hit_latency: Option<u64>,
+
+    /// L3 miss penalty: cycles a miss adds on top of the level below
+    #[arg(long, value_name = "CYCLES")]
+    pub l3_miss_penalty: Option<u64>,
+
+    /// Cycles for main memory to return a line
+    #[arg(long, value_name = "CYCLES")]
+    pub main_latency: Option<u64>,
+
+    /// Seed for the random eviction policy; the same seed replays the same run
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+
+    /// Write buffer entries in front of main memory; adds one when the config has none
+    #[arg(long, value_name = "ENTRIES")]
+    pub write_buffer: Option<usize>,
+
+    /// When the write buffer retires entries (lazy, eager)
+    #[arg(long, value_name = "POLICY")]
+    pub write_buffer_drain: Option<DrainPolicy>,
+
+    /// Entries an eager write buffer leaves undrained
+    #[arg(long, value_name = "ENTRIES")]
+    pub write_buffer_high_water: Option<usize>,
+
+    /// How the final statistics are printed
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Write the csv or json report to FILE instead of stdout
+    #[arg(long, short, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+/* the per-level flags of one cache, None where not given */
+struct CacheFlags {
+    bytes:          Option<usize>,
+    words_per_line: Option<usize>,
+    assoc:          Option<usize>,
+    policy:         Option<EvictionPolicy>,
+    write_hit:      Option<WriteHit>,
+    write_miss:     Option<WriteMiss>,
+    hit_latency:    Option<u64>,
+    miss_penalty:   Option<u64>,
+}
+
+impl Args {
+    /// Layer the flags that were given on top of `config`.
+    pub fn apply(&self, config: &mut MemoryConfig) -> Result<(), ConfigError> {
+        if let Some(routing) = self.routing { config.routing = Some(routing); }
+        if let Some(base) = self.dm_base   { config.dm_base = base; }
+        if let Some(base) = self.mmio_base { config.mmio_base = base; }
+        if let Some(size) = self.mem_size  { config.main_bytes = size; }
+        if let Some(seed) = self.seed      { config.seed = seed; }
+        if let Some(cycles) = self.main_latency { config.main_latency = cycles; }
+
+        self.l1i_flags().apply(&mut config.im);
+        self.l1d_flags().apply(&mut config.dm);
+
+        /* a new lower level starts direct mapped with the widest line above it */
+        let above = config.im.words_per_line.max(config.dm.words_per_line);
+        self.l2_flags().apply_level(&mut config.l2, "l2", above)?;
+
+        let above = config.l2.as_ref().map_or(above, |l2| l2.words_per_line);
+        self.l3_flags().apply_level(&mut config.l3, "l3", above)?;
+
+        self.apply_write_buffer(&mut config.write_buffer)
+    }
+
+    /* like a lower level, the buffer only comes into being through its size flag */
+    fn apply_write_buffer(&self, buffer: &mut Option<WriteBufferConfig>) -> Result<(), ConfigError> {
+        if let Some(entries) = self.write_buffer {
+            buffer.get_or_insert(WriteBufferConfig::new(entries)).entries = entries;
+        }
+
+        match buffer {
+            Some(wb) => {
+                if let Some(d) = self.write_buffer_drain      { wb.drain = d; }
+                if let Some(h) = self.write_buffer_high_water { wb.high_water = h; }
+                Ok(())
+            }
+            None if self.write_buffer_drain.is_some() || self.write_buffer_high_water.is_some() => {
+                Err(ConfigError::invalid("write_buffer", "no write buffer configured; add --write-buffer"))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Args {
+    fn l1i_flags(&self) -> CacheFlags {
+        CacheFlags {
+            bytes: self.l1i_size, words_per_line: self.l1i_words_per_line, assoc: self.l1i_assoc,
+            policy: self.l1i_policy, write_hit: self.l1i_write_hit, write_miss: self.l1i_write_miss,
+            hit_latency: self.l1i_hit_latency, miss_penalty: self.l1i_miss_penalty,
+        }
+    }
+
+    fn l1d_flags(&self) -> CacheFlags {
+        CacheFlags {
+            bytes: self.l1d_size, words_per_line: self.l1d_words_per_line, assoc: self.l1d_assoc,
+            policy: self.l1d_policy, write_hit: self.l1d_write_hit, write_miss: self.l1d_write_miss,
+            hit_latency: self.l1d_hit_latency, miss_penalty: self.l1d_miss_penalty,
+        }
+    }
+
+    fn l2_flags(&self) -> CacheFlags {
+        CacheFlags {
+            bytes: self.l2_size, words_per_line: self.l2_words_per_line, assoc: self.l2_assoc,
+            policy: self.l2_policy, write_hit: self.l2_write_hit, write_miss: self.l2_write_miss,
+            hit_latency: self.l2_hit_latency, miss_penalty: self.l2_miss_penalty,
+        }
+    }
+
+    fn l3_flags(&self) -> CacheFlags {
+        CacheFlags {
+            bytes: self.l3_size, words_per_line: self.l3_words_per_line, assoc: self.l3_assoc,
+            policy: self.l3_policy, write_hit: self.l3_write_hit, write_miss: self.l3_write_miss,
+            hit_latency: self.l3_hit_latency, miss_penalty: self.l3_miss_penalty,
+        }
+    }
+}
+
+impl CacheFlags {
+    fn apply(&self, cache: &mut CacheConfig) {
+        if let Some(b) = self.bytes          { cache.bytes = b; }
+        if let Some(w) = self.words_per_line { cache.words_per_line = w; }
+        if let Some(a) = self.assoc          { cache.assoc = a; }
+        if let Some(p) = self.policy         { cache.policy = p; }
+        if let Some(h) = self.write_hit      { cache.write_hit = h; }
+        if let Some(m) = self.write_miss     { cache.write_miss = m; }
+        if let Some(h) = self.hit_latency    { cache.hit_latency = h; }
+        if let Some(p) = self.miss_penalty   { cache.miss_penalty = p; }
+    }
+
+    /* an optional lower level only comes into being through its size flag */
+    fn apply_level(&self, level: &mut Option<CacheConfig>, name: &str, words_per_line_above: usize) -> Result<(), ConfigError> {
+        if level.is_none() {
+            match self.bytes {
+                Some(b) => *level = Some(CacheConfig::direct_mapped(b, words_per_line_above)),
+                None if self.any_given() => {
+                    return Err(ConfigError::invalid(name, format!("no {name} configured; add --{name}-size")));
+                }
+                None => return Ok(()),
+            }
+        }
+
+        if let Some(cache) = level {
+            self.apply(cache);
+        }
+        Ok(())
+    }
+
+    fn any_given(&self) -> bool {
+        self.words_per_line.is_some() || self.assoc.is_some() || self.policy.is_some()
+            || self.write_hit.is_some() || self.write_miss.is_some()
+            || self.hit_latency.is_some() || self.miss_penalty.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_config() {
+        let args = Args::parse_from([
+            "cache_sim", "trace.txt", "--routing", "kind", "--dm-base", "0x1000", "--l1d-assoc", "4", "--l1d-policy", "nru",
+            "--l1d-write-hit", "through", "--l1d-write-miss", "around",
+        ]);
+        let mut config = MemoryConfig {
+            main_bytes: 1 << 16,
+            im: CacheConfig::direct_mapped(1 << 10, 4),
+            dm: CacheConfig::direct_mapped(1 << 10, 4),
+            dm_base: 0x800,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        };
+        args.apply(&mut config).unwrap();
+
+        assert_eq!((config.routing, config.dm_base), (Some(Routing::Kind), 0x1000));
+        assert_eq!(config.dm.assoc, 4);
+        assert_eq!(config.dm.policy, EvictionPolicy::Nru);
+        assert_eq!((config.dm.write_hit, config.dm.write_miss), (WriteHit::Through, WriteMiss::Around));
+        assert_eq!(config.im, CacheConfig::direct_mapped(1 << 10, 4));
+        assert_eq!(config.l2, None);
+    }
+
+    #[test]
+    fn size_flag_adds_a_level() {
+        let base = MemoryConfig {
+            main_bytes: 1 << 16,
+            im: CacheConfig::direct_mapped(1 << 10, 8),
+            dm: CacheConfig::direct_mapped(1 << 10, 4),
+            dm_base: 0x800,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        };
+
+        let mut config = base.clone();
+        Args::parse_from(["cache_sim", "t", "--l2-size", "64K", "--l2-assoc", "8"]).apply(&mut config).unwrap();
+        assert_eq!(config.l2, Some(CacheConfig::set_associative(64 << 10, 8, 8, EvictionPolicy::Lru)));
+        assert_eq!(config.l3, None);
+
+        let mut config = base.clone();
+        let orphan = Args::parse_from(["cache_sim", "t", "--l3-policy", "nru"]).apply(&mut config);
+        assert!(matches!(orphan, Err(ConfigError::Invalid { field, .. }) if field == "l3"));
+
+        let mut config = base.clone();
+        Args::parse_from(["cache_sim", "t", "--write-buffer", "8", "--write-buffer-drain", "eager"])
+            .apply(&mut config).unwrap();
+        assert_eq!(config.write_buffer.map(|wb| (wb.entries, wb.drain)), Some((8, DrainPolicy::Eager)));
+
+        let mut config = base.clone();
+        let orphan = Args::parse_from(["cache_sim", "t", "--write-buffer-high-water", "2"]).apply(&mut config);
+        assert!(matches!(orphan, Err(ConfigError::Invalid { field, .. }) if field == "write_buffer"));
+    }
+}
@@ -0,0 +1,398 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::cache::{CacheConfig, EvictionPolicy};
+use crate::memory::MemoryConfig;
+
+/* ── errors ──────────────────────────────────────────────────────────── */
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    Invalid { field: String, reason: String },
+}
+
+impl ConfigError {
+    pub fn invalid(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        ConfigError::Invalid { field: field.into(), reason: reason.into() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e)         => write!(f, "{}: {e}", path.display()),
+            ConfigError::Parse(path, msg)    => write!(f, "{}: {msg}", path.display()),
+            ConfigError::Invalid { field, reason } => write!(f, "{field}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/* ── loading ─────────────────────────────────────────────────────────── */
+
+/// Read a hierarchy description from a `.toml` or `.json` file and validate it.
+/// Files with any other extension are parsed as TOML.
+pub fn load(path: &Path) -> Result<MemoryConfig, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+
+    let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let config: MemoryConfig = if is_json {
+        serde_json::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?
+    } else {
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?
+    };
+
+    config.validate()?;
+    Ok(config)
+}
+
+/* ── validation ──────────────────────────────────────────────────────── */
+
+impl MemoryConfig {
+    /// Check every geometry constraint the simulator relies on, naming the
+    /// first field that breaks one.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.main_bytes.is_power_of_two() {
+            return Err(ConfigError::invalid(
+                "main_bytes", format!("must be a power of two (got {})", self.main_bytes),
+            ));
+        }
+        if self.dm_base >= self.main_bytes {
+            return Err(ConfigError::invalid(
+                "dm_base", format!("{:#x} lies past the end of main memory ({:#x} bytes)", self.dm_base, self.main_bytes),
+            ));
+        }
+        if self.dm_base > self.mmio_base {
+            return Err(ConfigError::invalid(
+                "dm_base", format!("{:#x} lies above mmio_base {:#x}", self.dm_base, self.mmio_base),
+            ));
+        }
+
+        self.im.validate("l1i")?;
+        self.dm.validate("l1d")?;
+
+        if let Some(l2) = &self.l2 { l2.validate("l2")?; }
+        if let Some(l3) = &self.l3 { l3.validate("l3")?; }
+        if self.l3.is_some() && self.l2.is_none() {
+            return Err(ConfigError::invalid("l3", "needs an l2 level above it"));
+        }
+
+        /* a lower level must hold whole lines of every level that fills from it */
+        let mut above = ("l1", self.im.words_per_line.max(self.dm.words_per_line));
+        for (level, cache) in [("l2", &self.l2), ("l3", &self.l3)] {
+            let Some(cache) = cache else { break };
+            if cache.words_per_line < above.1 {
+                return Err(ConfigError::invalid(
+                    format!("{level}.words_per_line"),
+                    format!("must be at least the {} line size ({} words, got {})",
+                        above.0, above.1, cache.words_per_line),
+                ));
+            }
+            above = (level, cache.words_per_line);
+        }
+
+        if let Some(wb) = &self.write_buffer {
+            if wb.entries == 0 {
+                return Err(ConfigError::invalid("write_buffer.entries", "must be at least 1"));
+            }
+            if wb.high_water >= wb.entries {
+                return Err(ConfigError::invalid(
+                    "write_buffer.high_water",
+                    format!("must be below entries ({}, got {})", wb.entries, wb.high_water),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Every cache level in order: l1i, l1d, then l2 and l3 when present.
+    pub fn caches(&self) -> impl Iterator<Item = &CacheConfig> {
+        [Some(&self.im), Some(&self.dm), self.l2.as_ref(), self.l3.as_ref()].into_iter().flatten()
+    }
+
+    /// Whether any cache level evicts with `policy`.
+    pub fn uses_policy(&self, policy: EvictionPolicy) -> bool {
+        self.caches().any(|cache| cache.policy == policy)
+    }
+}
+
+impl CacheConfig {
+    pub fn validate(&self, level: &str) -> Result<(), ConfigError> {
+        let field = |name: &str| format!("{level}.{name}");
+
+        if !self.bytes.is_power_of_two() {
+            return Err(ConfigError::invalid(
+                field("bytes"), format!("must be a power of two (got {})", self.bytes),
+            ));
+        }
+        if !self.words_per_line.is_power_of_two() {
+            return Err(ConfigError::invalid(
+                field("words_per_line"), format!("must be a power of two (got {})", self.words_per_line),
+            ));
+        }
+        if self.line_bytes() > self.bytes {
+            return Err(ConfigError::invalid(
+                field("words_per_line"),
+                format!("a {}-byte line does not fit in {} bytes", self.line_bytes(), self.bytes),
+            ));
+        }
+        if self.ways() > self.num_lines() {
+            return Err(ConfigError::invalid(
+                field("assoc"), format!("must be between 1 and {} (got {})", self.num_lines(), self.assoc),
+            ));
+        }
+        if self.is_fully_associative() && self.policy.is_rrip() {
+            return Err(ConfigError::invalid(
+                field("policy"), format!("{} needs a set-associative cache", self.policy),
+            ));
+        }
+        if !self.num_lines().is_multiple_of(self.ways()) || !self.num_sets().is_power_of_two() {
+            return Err(ConfigError::invalid(
+                field("assoc"),
+                format!("{} lines do not split into a power-of-two number of {}-way sets",
+                    self.num_lines(), self.ways()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/* ── value parsing shared with the CLI ───────────────────────────────── */
+
+/// Hex address, with or without a `0x` prefix; `_` separators allowed.
+pub fn parse_addr(s: &str) -> Result<usize, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X").replace('_', "");
+    usize::from_str_radix(&digits, 16).map_err(|e| format!("bad hex address '{s}': {e}"))
+}
+
+/// Byte count: decimal, `0x` hex, or decimal with a K/M/G/T (or KiB/MiB/GiB/TiB) suffix.
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let t = s.trim().replace('_', "");
+    if let Some(hex) = t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+        return usize::from_str_radix(hex, 16).map_err(|e| format!("bad size '{s}': {e}"));
+    }
+
+    let split = t.find(|c: char| !c.is_ascii_digit()).unwrap_or(t.len());
+    let (num, unit) = t.split_at(split);
+    let shift = match unit.to_ascii_lowercase().as_str() {
+        ""                 => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => return Err(format!("bad size '{s}': unknown unit '{unit}'")),
+    };
+    let n: usize = num.parse().map_err(|e| format!("bad size '{s}': {e}"))?;
+    n.checked_mul(1 << shift).ok_or_else(|| format!("size '{s}' overflows"))
+}
+
+/// Associativity: a way count, or `full` for a fully-associative cache.
+pub fn parse_assoc(s: &str) -> Result<usize, String> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "full" | "fa" => Ok(CacheConfig::FULLY_ASSOCIATIVE),
+        "0" => Err("associativity must be at least 1 (use 'full' for fully associative)".into()),
+        t => t.parse().map_err(|e| format!("bad associativity '{s}': {e}")),
+    }
+}
+
+/* sizes and addresses may be written as plain integers or as strings
+ * ("16KiB", "0x0060_0000"); JSON has no hex literals */
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IntOrStr {
+    Int(usize),
+    Str(String),
+}
+
+pub(crate) fn de_size<'de, D: Deserializer<'de>>(d: D) -> Result<usize, D::Error> {
+    match IntOrStr::deserialize(d)? {
+        IntOrStr::Int(n) => Ok(n),
+        IntOrStr::Str(s) => parse_size(&s).map_err(serde::de::Error::custom),
+    }
+}
+
+pub(crate) fn de_assoc<'de, D: Deserializer<'de>>(d: D) -> Result<usize, D::Error> {
+    match IntOrStr::deserialize(d)? {
+        IntOrStr::Int(0) => Err(serde::de::Error::custom("associativity must be at least 1 (use \"full\")")),
+        IntOrStr::Int(n) => Ok(n),
+        IntOrStr::Str(s) => parse_assoc(&s).map_err(serde::de::Error::custom),
+    }
+}
+
+pub(crate) fn ser_assoc<S: Serializer>(assoc: &usize, s: S) -> Result<S::Ok, S::Error> {
+    match *assoc {
+        CacheConfig::FULLY_ASSOCIATIVE => s.serialize_str("full"),
+        n => s.serialize_u64(n as u64),
+    }
+}
+
+pub(crate) fn de_addr<'de, D: Deserializer<'de>>(d: D) -> Result<usize, D::Error> {
+    match IntOrStr::deserialize(d)? {
+        IntOrStr::Int(n) => Ok(n),
+        IntOrStr::Str(s) => parse_addr(&s).map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{WriteHit, WriteMiss};
+    use crate::write_buffer::DrainPolicy;
+
+    const DEFAULT_TOML: &str = include_str!("../configs/default.toml");
+
+    fn parse_toml(text: &str) -> Result<MemoryConfig, ConfigError> {
+        let config: MemoryConfig = toml::from_str(text)
+            .map_err(|e| ConfigError::Parse(PathBuf::from("<test>"), e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn invalid_field(result: Result<MemoryConfig, ConfigError>) -> String {
+        match result {
+            Err(ConfigError::Invalid { field, .. }) => field,
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("16K"), Ok(16 << 10));
+        assert_eq!(parse_size("8KiB"), Ok(8 << 10));
+        assert_eq!(parse_size("4MiB"), Ok(4 << 20));
+        assert_eq!(parse_size("128TiB"), Ok(1 << 47));
+        assert_eq!(parse_size("0x4000"), Ok(0x4000));
+        assert!(parse_size("12 parsecs").is_err());
+    }
+
+    #[test]
+    fn addrs() {
+        assert_eq!(parse_addr("0x0060_0000"), Ok(0x60_0000));
+        assert_eq!(parse_addr("A0000000"), Ok(0xA000_0000));
+        assert!(parse_addr("0xnope").is_err());
+    }
+
+    #[test]
+    fn assoc_values() {
+        assert_eq!(parse_assoc("4"), Ok(4));
+        assert_eq!(parse_assoc("full"), Ok(CacheConfig::FULLY_ASSOCIATIVE));
+        assert!(parse_assoc("0").is_err());
+
+        let fa = DEFAULT_TOML.replace("assoc          = 2", "assoc          = \"full\"");
+        let config = parse_toml(&fa).unwrap();
+        assert!(config.im.is_fully_associative());
+        assert_eq!(config.im.ways(), config.im.num_lines());
+    }
+
+    #[test]
+    fn default_file_parses() {
+        let config = parse_toml(DEFAULT_TOML).expect("configs/default.toml must be valid");
+
+        assert_eq!(config.main_bytes, 1 << 47);
+        assert_eq!(config.dm_base, 0x0060_0000);
+        assert_eq!(config.mmio_base, 0xFFFF_8000_0000_0000);
+        assert_eq!(config.im, CacheConfig::set_associative(1 << 14, 8, 2, EvictionPolicy::Lru));
+        assert_eq!(config.dm, CacheConfig::direct_mapped(1 << 13, 4));
+    }
+
+    #[test]
+    fn json_matches_toml() {
+        let json = r#"{
+            "main_bytes": "128TiB",
+            "dm_base": "0x00600000",
+            "mmio_base": "0xFFFF800000000000",
+            "l1i": { "bytes": "16KiB", "words_per_line": 8, "assoc": 2, "policy": "lru" },
+            "l1d": { "bytes": 8192, "words_per_line": 4 }
+        }"#;
+        let from_json: MemoryConfig = serde_json::from_str(json).unwrap();
+        from_json.validate().unwrap();
+
+        let from_toml = parse_toml(DEFAULT_TOML).unwrap();
+        assert_eq!(from_json, from_toml);
+    }
+
+    #[test]
+    fn bad_fields_are_named() {
+        let bad_size = DEFAULT_TOML.replace("bytes          = \"8KiB\"", "bytes          = 3000");
+        assert_eq!(invalid_field(parse_toml(&bad_size)), "l1d.bytes");
+
+        let bad_line = DEFAULT_TOML.replace("words_per_line = 8", "words_per_line = 6");
+        assert_eq!(invalid_field(parse_toml(&bad_line)), "l1i.words_per_line");
+
+        let bad_assoc = DEFAULT_TOML.replace("assoc          = 2", "assoc          = 3");
+        assert_eq!(invalid_field(parse_toml(&bad_assoc)), "l1i.assoc");
+
+        let bad_main = DEFAULT_TOML.replace("main_bytes = \"128TiB\"", "main_bytes = 5000");
+        assert_eq!(invalid_field(parse_toml(&bad_main)), "main_bytes");
+
+        let small_main = DEFAULT_TOML.replace("main_bytes = \"128TiB\"", "main_bytes = \"4MiB\"");
+        assert_eq!(invalid_field(parse_toml(&small_main)), "dm_base");
+
+        let fa_rrip = DEFAULT_TOML.replace("assoc          = 2", "assoc          = \"full\"")
+            .replace("policy         = \"lru\"", "policy         = \"drrip\"");
+        assert_eq!(invalid_field(parse_toml(&fa_rrip)), "l1i.policy");
+
+        let bad_regions = DEFAULT_TOML.replace("0xFFFF_8000_0000_0000", "0x0000_1000");
+        assert_eq!(invalid_field(parse_toml(&bad_regions)), "dm_base");
+    }
+
+    #[test]
+    fn lower_levels() {
+        let with_l2 = format!("{DEFAULT_TOML}\n[l2]\nbytes = \"256KiB\"\nwords_per_line = 8\nassoc = 8\n");
+        let config = parse_toml(&with_l2).unwrap();
+        assert_eq!(config.l2, Some(CacheConfig::set_associative(256 << 10, 8, 8, EvictionPolicy::Lru)));
+
+        let narrow_l2 = with_l2.replace("words_per_line = 8\nassoc = 8", "words_per_line = 4\nassoc = 8");
+        assert_eq!(invalid_field(parse_toml(&narrow_l2)), "l2.words_per_line");
+
+        let three = parse_toml(include_str!("../configs/three_level.toml")).unwrap();
+        assert_eq!(three.l3.map(|l3| (l3.policy, l3.hit_latency)), Some((EvictionPolicy::Nru, 40)));
+        assert_eq!((three.im.hit_latency, three.main_latency), (1, 200));
+
+        let orphan_l3 = format!("{DEFAULT_TOML}\n[l3]\nbytes = \"2MiB\"\nwords_per_line = 8\n");
+        assert_eq!(invalid_field(parse_toml(&orphan_l3)), "l3");
+    }
+
+    #[test]
+    fn write_policies() {
+        let config = parse_toml(&DEFAULT_TOML.replace(
+            "assoc          = 1 ", "write_hit = \"through\"\nwrite_miss = \"around\"\nassoc          = 1 ",
+        )).unwrap();
+        assert_eq!(config.dm.write_hit, WriteHit::Through);
+        assert_eq!(config.dm.write_miss, WriteMiss::Around);
+        assert_eq!((config.im.write_hit, config.im.write_miss), (WriteHit::Back, WriteMiss::Allocate));
+
+        assert!(toml::from_str::<MemoryConfig>(&DEFAULT_TOML.replace("policy         = \"lru\"", "write_hit = \"sideways\"")).is_err());
+    }
+
+    #[test]
+    fn write_buffer_section() {
+        let with_wb = format!("{DEFAULT_TOML}\n[write_buffer]\nentries = 8\ndrain = \"eager\"\nhigh_water = 2\n");
+        let wb = parse_toml(&with_wb).unwrap().write_buffer.unwrap();
+        assert_eq!((wb.entries, wb.drain, wb.high_water), (8, DrainPolicy::Eager, 2));
+
+        let too_high = with_wb.replace("high_water = 2", "high_water = 8");
+        assert_eq!(invalid_field(parse_toml(&too_high)), "write_buffer.high_water");
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let typo = DEFAULT_TOML.replace("words_per_line = 8", "word_per_line = 8");
+        assert!(matches!(parse_toml(&typo), Err(ConfigError::Parse(..))));
+    }
+}
@@ -2,59 +2,83 @@
 use crate::mem_stats::*;
 use crate::memory::*;
 use crate::cache::*;
+use crate::three_c::MissClassifier;
 
 const WORDSIZE: usize = DataTypeSize::get_size(DataTypeSize::Word);
-pub const ADDR_BITS: usize = 32;
 
 #[derive(Debug)]
-pub struct DMCache<
-    const BYTES: usize,               
-    const WORDS_PER_LINE: usize,      
-    > {
+pub struct DMCache {
     lines: Vec<CacheLine>,
-    stats: MemStats,
+    words_per_line: usize,
+    pub(crate) stats: MemStats,
+    classifier: MissClassifier,
 }
 
 
-impl<const BYTES: usize, const WORDS_PER_LINE: usize> DMCache<BYTES, WORDS_PER_LINE> {
-    pub const NUM_LINES: usize = BYTES / (WORDSIZE * WORDS_PER_LINE);
+impl DMCache {
+    pub fn new(bytes: usize, words_per_line: usize) -> Self {
+        assert!(bytes.is_power_of_two(), "bytes must be a power of two");
+        assert!(words_per_line.is_power_of_two(), "words_per_line must be a power of two");
+        let num_lines = bytes / (WORDSIZE * words_per_line);
+        assert!(num_lines > 0, "cache must hold ≥ 1 line");
+        assert!(num_lines.is_power_of_two(),"num_lines must be a power of two");
 
-    pub fn new() -> Self {
-        assert!(BYTES.is_power_of_two(), "BYTES must be a power of two");
-        assert!(WORDS_PER_LINE.is_power_of_two(), "WORDS_PER_LINE must be a power of two");
-        assert!(Self::NUM_LINES > 0, "cache must hold ≥ 1 line");
-        assert!(Self::NUM_LINES.is_power_of_two(),"NUM_LINES must be a power of two");
-
-        let lines = vec![CacheLine::new(WORDS_PER_LINE); Self::NUM_LINES];
+        let lines = vec![CacheLine::new(words_per_line); num_lines];
         
-        Self { lines, stats: MemStats::new() }
+        Self {
+            lines, words_per_line, stats: MemStats::new(),
+            classifier: MissClassifier::new(num_lines, WORDSIZE * words_per_line),
+        }
+    }
+
+    pub fn num_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    /* the resident line holding addr */
+    pub(crate) fn line_mut(&mut self, addr: usize) -> Option<&mut CacheLine> {
+        let (tag, ind, ..) = self.decode_addr(addr);
+        let line = &mut self.lines[ind];
+        (line.is_valid() && line.tag() == tag).then_some(line)
     }
 
     pub fn print_summary(&self) {
         self.stats.print_summary();
     }
-}
 
-impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
-    fn read(&mut self, addr: usize, size: DataTypeSize, dont_count: bool) -> Result<DataType, MemoryError> {
-        let (_, ind, word, byte) = self.decode_addr(addr);
+    /* index of the line holding addr, counting the hit or classifying the miss */
+    fn lookup(&mut self, addr: usize, op: Access, dont_count: bool) -> Option<usize> {
+        let (tag, ind, ..) = self.decode_addr(addr);
+        let line = &self.lines[ind];
 
-        let line: &CacheLine = &self.lines[ind];
+        if !line.is_valid() || line.tag() != tag {
+            self.stats.record_miss(op);
+            self.stats.record_miss_kind(self.classifier.classify(addr));
+            return None;
+        }
 
-        if !line.is_valid() || line.tag() != self.get_tag(addr) {
-            self.stats.record_miss();
-            return Err(MemoryError::NotFound);
+        if !dont_count {
+            self.stats.record_hit(op);
+            self.classifier.touch(addr);
         }
-        
-        if !dont_count {self.stats.record_hit();} // TODO: dont increment if miss from above
-        
+        Some(ind)
+    }
+}
+
+impl CacheAccess for DMCache {
+    fn read(&mut self, addr: usize, size: DataTypeSize, dont_count: bool) -> Lookup<DataType> {
+        let (_, _, word, byte) = self.decode_addr(addr);
+        let Some(ind) = self.lookup(addr, Access::Read, dont_count) else { return Lookup::Miss };
+        self.stats.record_read(size.clone().size());
+        let line: &CacheLine = &self.lines[ind];
+
         let byte_index = WORDSIZE * word + byte;
 
         match size {
             DataTypeSize::Byte => {
                 let byte = line.read_byte(byte_index);
                 let ret_data= DataType::Byte(byte); 
-                Ok(ret_data)
+                Lookup::Hit(ret_data)
             }
 
             DataTypeSize::Halfword => {
@@ -63,7 +87,7 @@ impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
                     line.read_byte(byte_index + 1)
                 ]);
                 let ret_data= DataType::Halfword(val); 
-                Ok(ret_data)
+                Lookup::Hit(ret_data)
             }
 
             DataTypeSize::Word => {
@@ -74,7 +98,7 @@ impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
                     line.read_byte(byte_index + 3)
                 ]);
                 let ret_data = DataType::Word(val);
-                Ok(ret_data)
+                Lookup::Hit(ret_data)
             }
 
             DataTypeSize::DoubleWord => {
@@ -89,7 +113,7 @@ impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
                     line.read_byte(byte_index + 7)
                 ]);
                 let ret_data = DataType::DoubleWord(val);
-                Ok(ret_data)
+                Lookup::Hit(ret_data)
             }
         }
     }
@@ -98,24 +122,19 @@ impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
         &self.stats
     }
 
-    fn write(&mut self, data: DataType, addr: usize, dont_count: bool) -> Result<(), MemoryError> {
-        let (_, ind, word, byte) = self.decode_addr(addr);
+    fn write(&mut self, data: DataType, addr: usize, dont_count: bool) -> Lookup<()> {
+        let (_, _, word, byte) = self.decode_addr(addr);
         
         let byte_index = WORDSIZE * word + byte;
 
-        let line: &CacheLine = &self.lines[ind];
-        if !line.is_valid() || line.tag() != self.get_tag(addr) {
-            self.stats.record_miss();
-            return Err(MemoryError::NotFound);
-        }
-        
-        if !dont_count {self.stats.record_hit();}
+        let Some(ind) = self.lookup(addr, Access::Write, dont_count) else { return Lookup::Miss };
+        self.stats.record_write(data.payload_size());
         
         let line: &mut CacheLine = &mut self.lines[ind];
         match data {
             DataType::Byte(val) => {
                 line.write_byte(byte_index, val);
-                Ok(())
+                Lookup::Hit(())
             }
 
             DataType::Halfword(val) => {
@@ -123,7 +142,7 @@ impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
                 for (i, _) in bytes.iter().enumerate() {
                     line.write_byte(byte_index + i, bytes[i]);
                 }
-                Ok(())
+                Lookup::Hit(())
             }
 
 
@@ -133,7 +152,7 @@ impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
                 for (i, _) in bytes.iter().enumerate() {
                     line.write_byte(byte_index + i, bytes[i]);
                 }
-                Ok(())
+                Lookup::Hit(())
             }
 
             DataType::DoubleWord(val) => {
@@ -141,26 +160,46 @@ impl<const B: usize, const W: usize> MemoryAccess for DMCache<B, W> {
                 for (i, _) in bytes.iter().enumerate() {
                     line.write_byte(byte_index + i, bytes[i]);
                 }
-                Ok(())
+                Lookup::Hit(())
             }
         }
     }
 
 }
 
-impl<const B: usize, const W: usize> MemLevelAccess for DMCache<B, W> {
+impl MemLevelAccess for DMCache {
     fn write_line(&mut self, addr: usize, _words_per_lines: usize, data: Vec<u8>) {
         let (tag, ind, _, _) = self.decode_addr(addr);
+        let line_bytes = WORDSIZE * self.words_per_line;
         let line: &mut CacheLine = &mut self.lines[ind];
+        if line.is_valid() {
+            self.stats.record_eviction(line.is_dirty(), line_bytes);
+        }
+        self.stats.record_fill(line_bytes);
         line.write_line(tag, data);
     }
 
-    fn fetch_line(&self, addr: usize, _words_per_lines: usize) -> Vec<u8> {
-        self.get_evict_line_data(addr)
+    fn fetch_line(&mut self, addr: usize, words_per_lines: usize, dont_count: bool) -> Lookup<Vec<u8>> {
+        let (_, _, word, _) = self.decode_addr(addr);
+        let Some(ind) = self.lookup(addr, Access::Read, dont_count) else { return Lookup::Miss };
+        self.stats.record_read(WORDSIZE * words_per_lines);
+
+        let line: &CacheLine = &self.lines[ind];
+        Lookup::Hit(line.read_bytes(WORDSIZE * word, WORDSIZE * words_per_lines))
+    }
+
+    fn writeback_line(&mut self, addr: usize, words_per_lines: usize, data: Vec<u8>, dont_count: bool) -> Lookup<()> {
+        let (_, _, word, _) = self.decode_addr(addr);
+        let Some(ind) = self.lookup(addr, Access::Write, dont_count) else { return Lookup::Miss };
+        self.stats.record_writeback(WORDSIZE * words_per_lines);
+
+        let line: &mut CacheLine = &mut self.lines[ind];
+        line.write_bytes(WORDSIZE * word, &data[..WORDSIZE * words_per_lines]);
+        Lookup::Hit(())
     }
 }
 
-impl<const B: usize,const W: usize,> CacheAddressing for DMCache<B, W> {
+impl CacheAddressing for DMCache {
     #[inline(always)]
     fn byte_bits(&self) -> usize {
         WORDSIZE.trailing_zeros() as usize
@@ -168,7 +207,7 @@ impl<const B: usize,const W: usize,> CacheAddressing for DMCache<B, W> {
     
     #[inline(always)]
     fn word_bits(&self) -> usize {
-        W.trailing_zeros() as usize
+        self.words_per_line.trailing_zeros() as usize
     }
 
     #[inline(always)]
@@ -176,6 +215,11 @@ impl<const B: usize,const W: usize,> CacheAddressing for DMCache<B, W> {
         self.lines.len().trailing_zeros() as usize
     }
 
+    #[inline(always)]
+    fn words_per_line(&self) -> usize {
+        self.words_per_line
+    }
+
     fn decode_addr(&self, addr: usize) -> (usize, usize, usize, usize) {
         let bb = self.byte_bits(); // lowest bits
         let wb = self.word_bits(); // next bits
@@ -250,15 +294,13 @@ mod tests {
 
     #[test]
     fn new () {
-        type L1 = DMCache<1024, 8>;           // Direct-mapped, 1 KiB, 32-B lines
-        let l1 = L1::new();
+        let l1 = DMCache::new(1024, 8);       // Direct-mapped, 1 KiB, 32-B lines
         println!("{:#?}", l1);
     }
 
     #[test]
     fn parse_addr() {
-        type L1 = DMCache<1024, 8>;           // Direct-mapped, 1 KiB, 32-B lines
-        let c = L1::new();
+        let c = DMCache::new(1024, 8);        // Direct-mapped, 1 KiB, 32-B lines
 
         let addr = 0x385;
         let tag = c.get_tag(addr);
@@ -278,59 +320,73 @@ mod tests {
     fn compulsory_miss () {
         const L1_SIZE: usize = 1 << 12;
         const WORD_P_LINE: usize = 8;
-        type L1 = DMCache<L1_SIZE, WORD_P_LINE>;
-        let mut c = L1::new();
+        let mut c = DMCache::new(L1_SIZE, WORD_P_LINE);
 
         let addr = 0x385;
 
-        let result = c.read(addr, DataTypeSize::Byte);
+        let result = c.read(addr, DataTypeSize::Byte, false);
         
         assert!(
-            matches!(result, Err(MemoryError::NotFound)),
-            "expected Err(NotFound), got: {:?}",
+            matches!(result, Lookup::Miss),
+            "expected a miss, got: {:?}",
             result
         );
 
         assert_eq!(c.stats.total_accesses(), 1);
+        assert_eq!(c.stats.compulsory(), 1);
+    }
+
+    #[test]
+    fn aliasing_lines_miss_as_conflict () {
+        const L1_SIZE: usize = 1 << 10;
+        const WORD_P_LINE: usize = 8;
+        let mut c = DMCache::new(L1_SIZE, WORD_P_LINE);
+        let (a, alias) = (0x40, 0x40 + L1_SIZE);   // same index, two of 32 lines in use
+
+        for addr in [a, alias, a, alias] {
+            if c.read(addr, DataTypeSize::Word, false).is_miss() {
+                c.write_line(addr, WORD_P_LINE, vec![0; WORDSIZE * WORD_P_LINE]);
+            }
+        }
+        assert_eq!((c.stats.compulsory(), c.stats.capacity(), c.stats.conflict()), (2, 0, 2));
     }
 
     #[test]
     fn single_write () {
         const L1_SIZE: usize = 1 << 12;
         const WORD_P_LINE: usize = 8;
-        type L1 = DMCache<L1_SIZE, WORD_P_LINE>;
-        let mut c = L1::new();
+        let mut c = DMCache::new(L1_SIZE, WORD_P_LINE);
 
         let addr = 0x385;
         c.write_line(addr, 8, vec![0xff; WORDSIZE * 8]);
 
-        let _ = c.write(DataType::Byte(0x11), addr);
-        match c.read(addr, DataTypeSize::Byte) {
-            Ok(DataType::Byte(d)) => assert_eq!(d, 0x11),
+        let _ = c.write(DataType::Byte(0x11), addr, false);
+        match c.read(addr, DataTypeSize::Byte, false) {
+            Lookup::Hit(DataType::Byte(d)) => assert_eq!(d, 0x11),
             _ => panic!("Incorrect Read")
         }
 
-        let _ = c.write(DataType::Halfword(0x1234), addr);
-        match c.read(addr, DataTypeSize::Halfword) {
-            Ok(DataType::Halfword(d)) => assert_eq!(d, 0x1234),
+        let _ = c.write(DataType::Halfword(0x1234), addr, false);
+        match c.read(addr, DataTypeSize::Halfword, false) {
+            Lookup::Hit(DataType::Halfword(d)) => assert_eq!(d, 0x1234),
             _ => panic!("Incorrect Read")
         }
 
-        let _ = c.write(DataType::Word(0xcafebabe), addr);
-        match c.read(addr, DataTypeSize::Word) {
-            Ok(DataType::Word(d)) => assert_eq!(d, 0xcafebabe),
+        let _ = c.write(DataType::Word(0xcafebabe), addr, false);
+        match c.read(addr, DataTypeSize::Word, false) {
+            Lookup::Hit(DataType::Word(d)) => assert_eq!(d, 0xcafebabe),
             _ => panic!("Incorrect Read")
         }
         
-        let _ = c.write(DataType::DoubleWord(0x87654321cafebabe), addr);
-        match c.read(addr, DataTypeSize::DoubleWord) {
-            Ok(DataType::DoubleWord(d)) => assert_eq!(d, 0x87654321cafebabe),
+        let _ = c.write(DataType::DoubleWord(0x87654321cafebabe), addr, false);
+        match c.read(addr, DataTypeSize::DoubleWord, false) {
+            Lookup::Hit(DataType::DoubleWord(d)) => assert_eq!(d, 0x87654321cafebabe),
             _ => panic!("Incorrect Read")
         }
 
         assert_eq!(c.stats.total_accesses(), 8);
-        assert_eq!(c.stats.hit_rate(), (8 / 8) as f64);
-        assert_eq!(c.stats.miss_rate(), (0 / 8) as f64);
+        assert_eq!(c.stats.hit_rate(), 1.0);
+        assert_eq!(c.stats.miss_rate(), 0.0);
 
     }
 
@@ -338,47 +394,45 @@ mod tests {
     fn read () {
         const L1_SIZE: usize = 1 << 12;
         const WORD_P_LINE: usize = 8;
-        type L1 = DMCache<L1_SIZE, WORD_P_LINE>;
-        let mut c = L1::new();
+        let mut c = DMCache::new(L1_SIZE, WORD_P_LINE);
 
         let addr = 0x385;
         c.write_line(addr, 8, vec![0xa5; WORDSIZE * 8]);
 
-        match c.read(addr, DataTypeSize::Byte) {
-            Ok(DataType::Byte(d)) => assert_eq!(d, 0xa5),
+        match c.read(addr, DataTypeSize::Byte, false) {
+            Lookup::Hit(DataType::Byte(d)) => assert_eq!(d, 0xa5),
             _ => panic!("Incorrect Read")
         }
 
-        match c.read(addr, DataTypeSize::Halfword) {
-            Ok(DataType::Halfword(d)) => assert_eq!(d, 0xa5a5),
+        match c.read(addr, DataTypeSize::Halfword, false) {
+            Lookup::Hit(DataType::Halfword(d)) => assert_eq!(d, 0xa5a5),
             _ => panic!("Incorrect Read")
         }
 
-        match c.read(addr, DataTypeSize::Word) {
-            Ok(DataType::Word(d)) => assert_eq!(d, 0xa5a5a5a5),
+        match c.read(addr, DataTypeSize::Word, false) {
+            Lookup::Hit(DataType::Word(d)) => assert_eq!(d, 0xa5a5a5a5),
             _ => panic!("Incorrect Read")
         }
 
-        match c.read(addr, DataTypeSize::DoubleWord) {
-            Ok(DataType::DoubleWord(d)) => assert_eq!(d, 0xa5a5a5a5a5a5a5a5),
+        match c.read(addr, DataTypeSize::DoubleWord, false) {
+            Lookup::Hit(DataType::DoubleWord(d)) => assert_eq!(d, 0xa5a5a5a5a5a5a5a5),
             _ => panic!("Incorrect Read")
         }
 
         assert_eq!(c.stats.total_accesses(), 4);
-        assert_eq!(c.stats.hit_rate(), (4 / 4) as f64);
-        assert_eq!(c.stats.miss_rate(), (0 / 4) as f64);
+        assert_eq!(c.stats.hit_rate(), 1.0);
+        assert_eq!(c.stats.miss_rate(), 0.0);
     }
 
     #[test]
     fn write_read_cache_line () {
         const L1_SIZE: usize = 1 << 12;
         const WORD_P_LINE: usize = 8;
-        type L1 = DMCache<L1_SIZE, WORD_P_LINE>;
-        let mut c = L1::new();
+        let mut c = DMCache::new(L1_SIZE, WORD_P_LINE);
 
         for i in 0..WORD_P_LINE {
             let i = i * WORDSIZE;
-            let _ = c.read(i, DataTypeSize::Word);
+            let _ = c.read(i, DataTypeSize::Word, false);
         }
 
         c.stats.print_summary();
@@ -391,8 +445,7 @@ mod tests {
     fn write_read_whole_cache () {
         const L1_SIZE: usize = 1 << 12;
         const WORD_P_LINE: usize = 8;
-        type L1 = DMCache<L1_SIZE, WORD_P_LINE>;
-        let mut c = L1::new();
+        let mut c = DMCache::new(L1_SIZE, WORD_P_LINE);
 
         let vec: Vec<u8> = (0..L1_SIZE).map(|i| i as u8).collect();
 
@@ -406,9 +459,9 @@ mod tests {
             }
         }
 
-        for i in 0..L1_SIZE {
-            match c.read(i, DataTypeSize::Byte) {
-                Ok(DataType::Byte(d)) => assert_eq!(d, vec[i]),
+        for (i, &expected) in vec.iter().enumerate() {
+            match c.read(i, DataTypeSize::Byte, false) {
+                Lookup::Hit(DataType::Byte(d)) => assert_eq!(d, expected),
                 _ => panic!("Incorrect Read")
             }
         }
@@ -0,0 +1,374 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use crate::cache::EvictionPolicy;
+use crate::config;
+use crate::mem_stats::MemStats;
+use crate::memory::{DataType, Memory, MemoryAccess, MemoryConfig, MemoryError, MemoryErrorKind, Routing};
+use crate::trace::{size_of, store_of};
+
+/* ---------------- C interface ---------------- */
+/* lets an emulator core drive a hierarchy access by access instead of
+ * writing a trace: a handle is created from a config file, accesses are
+ * issued as they retire, and counters are read back per level. Every call
+ * reports a CacheSimStatus; the text of the last failure on the calling
+ * thread is kept for cache_sim_last_error. A panic never unwinds into the
+ * caller: it is caught at the boundary and reported as Internal.
+ * include/cache_sim.h is generated from this file with cbindgen (see
+ * cbindgen.toml) */
+
+/// A simulated hierarchy; opaque to C.
+pub struct CacheSim {
+    mem: Memory,
+}
+
+/// Outcome of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheSimStatus {
+    Ok = 0,
+    /// the access is not aligned to its own size
+    Misaligned = 1,
+    /// the access runs past the end of main memory
+    OutOfBounds = 2,
+    /// a NULL handle, a size other than 1/2/4/8, an unknown level name
+    InvalidArgument = 3,
+    /// a bug in the simulator; the handle must not be used again
+    Internal = 4,
+}
+
+/// Counters of one level, as in the csv/json reports.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheSimStats {
+    pub read_hits: u64,
+    pub read_misses: u64,
+    pub write_hits: u64,
+    pub write_misses: u64,
+    pub compulsory: u64,
+    pub capacity: u64,
+    pub conflict: u64,
+    pub writes: u64,
+    pub writebacks: u64,
+    pub bytes_written: u64,
+    pub fills: u64,
+    pub clean_evictions: u64,
+    pub dirty_writebacks: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub cycles: u64,
+    /// average memory access time in cycles
+    pub amat: f64,
+}
+
+impl From<&MemStats> for CacheSimStats {
+    fn from(stats: &MemStats) -> Self {
+        Self {
+            read_hits: stats.read_hits() as u64,
+            read_misses: stats.read_misses() as u64,
+            write_hits: stats.write_hits() as u64,
+            write_misses: stats.write_misses() as u64,
+            compulsory: stats.compulsory() as u64,
+            capacity: stats.capacity() as u64,
+            conflict: stats.conflict() as u64,
+            writes: stats.writes() as u64,
+            writebacks: stats.writebacks() as u64,
+            bytes_written: stats.bytes_written() as u64,
+            fills: stats.fills() as u64,
+            clean_evictions: stats.clean_evictions() as u64,
+            dirty_writebacks: stats.dirty_writebacks() as u64,
+            bytes_in: stats.bytes_in() as u64,
+            bytes_out: stats.bytes_out() as u64,
+            cycles: stats.cycles(),
+            amat: stats.amat(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(status: CacheSimStatus, message: impl ToString) -> CacheSimStatus {
+    let message = CString::new(message.to_string()).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    status
+}
+
+fn fail_access(e: MemoryError) -> CacheSimStatus {
+    let status = match e.kind {
+        MemoryErrorKind::NotAligned  => CacheSimStatus::Misaligned,
+        MemoryErrorKind::OutOfBounds => CacheSimStatus::OutOfBounds,
+    };
+    fail(status, e)
+}
+
+/* run an entry point's body; a panic is recorded as Internal and turned
+ * into `on_panic` instead of unwinding across the C boundary, which would
+ * abort the host */
+fn guard<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        fail(CacheSimStatus::Internal, format!("internal error: {}", panic_message(&*payload)));
+        on_panic
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "panic"
+    }
+}
+
+/* the handle behind sim, or InvalidArgument for NULL */
+unsafe fn handle<'a>(sim: *mut CacheSim) -> Result<&'a mut CacheSim, CacheSimStatus> {
+    unsafe { sim.as_mut() }.ok_or_else(|| fail(CacheSimStatus::InvalidArgument, "NULL cache_sim handle"))
+}
+
+fn value_of(data: DataType) -> u64 {
+    match data {
+        DataType::Byte(v)       => v.into(),
+        DataType::Halfword(v)   => v.into(),
+        DataType::Word(v)       => v.into(),
+        DataType::DoubleWord(v) => v,
+    }
+}
+
+/// Create a hierarchy from a `.toml` or `.json` file, or the default one
+/// for a NULL path. Returns NULL on failure; cache_sim_last_error says why.
+/// policy = "opt" is refused: it needs the whole trace up front.
+///
+/// # Safety
+/// `config_path` is NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cache_sim_new(config_path: *const c_char) -> *mut CacheSim {
+    guard(ptr::null_mut(), || {
+        let mut config = if config_path.is_null() {
+            MemoryConfig::default()
+        } else {
+            let path = unsafe { CStr::from_ptr(config_path) }.to_string_lossy();
+            match config::load(Path::new(path.as_ref())) {
+                Ok(config) => config,
+                Err(e)     => { fail(CacheSimStatus::InvalidArgument, e); return ptr::null_mut(); }
+            }
+        };
+        if config.uses_policy(EvictionPolicy::Opt) {
+            fail(CacheSimStatus::InvalidArgument, "policy opt needs the whole trace up front, which a live caller cannot give");
+            return ptr::null_mut();
+        }
+        /* the caller says which accesses are fetches */
+        config.routing.get_or_insert(Routing::Kind);
+        match Memory::new(&config) {
+            Ok(mem) => Box::into_raw(Box::new(CacheSim { mem })),
+            Err(e)  => { fail(CacheSimStatus::InvalidArgument, e); ptr::null_mut() }
+        }
+    })
+}
+
+/// Destroy a handle from cache_sim_new. NULL is ignored.
+///
+/// # Safety
+/// `sim` is NULL or a live handle, not used again afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cache_sim_free(sim: *mut CacheSim) {
+    if !sim.is_null() {
+        drop(unsafe { Box::from_raw(sim) });
+    }
+}
+
+/// Load `size` (1, 2, 4 or 8) bytes at addr through the D-side. The value
+/// read is stored to `value` unless it is NULL.
+///
+/// # Safety
+/// `sim` is a live handle; `value` is NULL or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cache_sim_read(sim: *mut CacheSim, addr: u64, size: u32, value: *mut u64) -> CacheSimStatus {
+    guard(CacheSimStatus::Internal, || {
+        let sim = match unsafe { handle(sim) } { Ok(sim) => sim, Err(status) => return status };
+        let size = match size_of(size as usize) { Ok(size) => size, Err(e) => return fail(CacheSimStatus::InvalidArgument, e) };
+        match sim.mem.read(addr as usize, size, false) {
+            Ok(data) => {
+                if let Some(value) = unsafe { value.as_mut() } {
+                    *value = value_of(data);
+                }
+                CacheSimStatus::Ok
+            }
+            Err(e) => fail_access(e),
+        }
+    })
+}
+
+/// Store the low `size` (1, 2, 4 or 8) bytes of value at addr.
+///
+/// # Safety
+/// `sim` is a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cache_sim_write(sim: *mut CacheSim, addr: u64, size: u32, value: u64) -> CacheSimStatus {
+    guard(CacheSimStatus::Internal, || {
+        let sim = match unsafe { handle(sim) } { Ok(sim) => sim, Err(status) => return status };
+        let data = match store_of(size as usize, value) { Ok(data) => data, Err(e) => return fail(CacheSimStatus::InvalidArgument, e) };
+        match sim.mem.write(data, addr as usize, false) {
+            Ok(())  => CacheSimStatus::Ok,
+            Err(e)  => fail_access(e),
+        }
+    })
+}
+
+/// Fetch `size` (1, 2, 4 or 8) bytes of instructions at addr.
+///
+/// # Safety
+/// `sim` is a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cache_sim_fetch(sim: *mut CacheSim, addr: u64, size: u32) -> CacheSimStatus {
+    guard(CacheSimStatus::Internal, || {
+        let sim = match unsafe { handle(sim) } { Ok(sim) => sim, Err(status) => return status };
+        let size = match size_of(size as usize) { Ok(size) => size, Err(e) => return fail(CacheSimStatus::InvalidArgument, e) };
+        match sim.mem.fetch(addr as usize, size, false) {
+            Ok(_)  => CacheSimStatus::Ok,
+            Err(e) => fail_access(e),
+        }
+    })
+}
+
+/// Retire whatever the write buffer still holds to main memory, as the
+/// binary does at the end of a trace.
+///
+/// # Safety
+/// `sim` is a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cache_sim_drain(sim: *mut CacheSim) -> CacheSimStatus {
+    guard(CacheSimStatus::Internal, || {
+        match unsafe { handle(sim) } {
+            Ok(sim) => { sim.mem.drain_write_buffer(); CacheSimStatus::Ok }
+            Err(status) => status,
+        }
+    })
+}
+
+/// Counters of one level so far: "memory" for the whole hierarchy, "l1i",
+/// "l1d", "l2", "l3" or "main".
+///
+/// # Safety
+/// `sim` is a live handle, `level` a NUL-terminated string and `out` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cache_sim_stats(sim: *mut CacheSim, level: *const c_char, out: *mut CacheSimStats) -> CacheSimStatus {
+    guard(CacheSimStatus::Internal, || {
+        let sim = match unsafe { handle(sim) } { Ok(sim) => sim, Err(status) => return status };
+        if level.is_null() || out.is_null() {
+            return fail(CacheSimStatus::InvalidArgument, "NULL level or stats pointer");
+        }
+        let level = unsafe { CStr::from_ptr(level) }.to_string_lossy();
+        let report = sim.mem.report();
+        match report.levels.iter().find(|row| row.level == level) {
+            Some(row) => { unsafe { *out = row.stats.into() }; CacheSimStatus::Ok }
+            None      => fail(CacheSimStatus::InvalidArgument, format!("no level '{level}' in this hierarchy")),
+        }
+    })
+}
+
+/// Why the last call on this thread failed, or NULL if none has. Valid until
+/// the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn cache_sim_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(sim: *mut CacheSim, level: &CStr) -> CacheSimStats {
+        let mut out = CacheSimStats::default();
+        assert_eq!(unsafe { cache_sim_stats(sim, level.as_ptr(), &mut out) }, CacheSimStatus::Ok);
+        out
+    }
+
+    #[test]
+    fn accesses_through_the_c_interface() {
+        unsafe {
+            let sim = cache_sim_new(ptr::null());
+            assert!(!sim.is_null());
+
+            assert_eq!(cache_sim_write(sim, 0x1000, 4, 0xcafe_babe), CacheSimStatus::Ok);
+            let mut value = 0;
+            assert_eq!(cache_sim_read(sim, 0x1000, 4, &mut value), CacheSimStatus::Ok);
+            assert_eq!(value, 0xcafe_babe);
+            assert_eq!(cache_sim_fetch(sim, 0x40, 4), CacheSimStatus::Ok);
+
+            let l1d = stats(sim, c"l1d");
+            assert_eq!((l1d.write_misses, l1d.read_hits), (1, 1));
+            assert_eq!(stats(sim, c"l1i").read_misses, 1);
+
+            cache_sim_free(sim);
+        }
+    }
+
+    #[test]
+    fn failures_leave_a_status_and_a_message() {
+        unsafe {
+            let sim = cache_sim_new(ptr::null());
+            let message = || CStr::from_ptr(cache_sim_last_error()).to_str().unwrap().to_owned();
+
+            assert_eq!(cache_sim_read(sim, 0x1002, 4, ptr::null_mut()), CacheSimStatus::Misaligned);
+            assert_eq!(message(), "l1d: 4-byte access at 0x1002 is misaligned");
+            assert_eq!(cache_sim_fetch(sim, 0x40, 3), CacheSimStatus::InvalidArgument);
+            assert_eq!(message(), "unsupported size 3");
+            assert_eq!(cache_sim_write(sim, 1 << 47, 4, 1), CacheSimStatus::OutOfBounds);
+            assert_eq!(message(), "main: 4-byte access at 0x800000000000 runs past the end of memory");
+
+            let mut out = CacheSimStats::default();
+            assert_eq!(cache_sim_stats(sim, c"l2".as_ptr(), &mut out), CacheSimStatus::InvalidArgument);
+            assert_eq!(cache_sim_read(ptr::null_mut(), 0, 4, ptr::null_mut()), CacheSimStatus::InvalidArgument);
+
+            assert!(cache_sim_new(c"no/such/config.toml".as_ptr()).is_null());
+            assert!(message().starts_with("no/such/config.toml"));
+
+            cache_sim_free(sim);
+        }
+    }
+
+    #[test]
+    fn opt_is_refused() {
+        let file = std::env::temp_dir().join(format!("cache_sim_{}_opt.toml", std::process::id()));
+        let opt = include_str!("../configs/default.toml").replace("policy         = \"lru\"", "policy         = \"opt\"");
+        std::fs::write(&file, opt).unwrap();
+        let path = CString::new(file.to_str().unwrap()).unwrap();
+
+        unsafe {
+            assert!(cache_sim_new(path.as_ptr()).is_null());
+            let message = CStr::from_ptr(cache_sim_last_error()).to_str().unwrap();
+            assert!(message.starts_with("policy opt needs the whole trace"), "{message}");
+        }
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn device_registers_are_not_cached() {
+        unsafe {
+            let sim = cache_sim_new(ptr::null());
+            let mmio_base = MemoryConfig::default().mmio_base as u64;
+
+            assert_eq!(cache_sim_write(sim, mmio_base, 4, 1), CacheSimStatus::Ok);
+            assert_eq!(cache_sim_read(sim, mmio_base + 4, 4, ptr::null_mut()), CacheSimStatus::Ok);
+            assert_eq!(cache_sim_read(sim, 0xffff_ffff_ffff_fff8, 8, ptr::null_mut()), CacheSimStatus::Ok);
+            assert_eq!(stats(sim, c"l1d"), CacheSimStats::default());
+
+            cache_sim_free(sim);
+        }
+    }
+
+    #[test]
+    fn panics_stop_at_the_boundary() {
+        let status = guard(CacheSimStatus::Internal, || panic!("lost a line"));
+        assert_eq!(status, CacheSimStatus::Internal);
+        let message = unsafe { CStr::from_ptr(cache_sim_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "internal error: lost a line");
+    }
+}
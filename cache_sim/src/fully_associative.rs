@@ -0,0 +1,461 @@
+use std::collections::{BTreeSet, HashMap};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    cache::{CacheAddressing, EvictionPolicy, CacheLine},
+    mem_stats::*,
+    memory::{CacheAccess, DataType, DataTypeSize, Lookup, MemLevelAccess},
+    opt::NextUse,
+    plru::TreePlru,
+};
+
+/* --------------------------------------------------------------------- */
+
+const WORDSIZE: usize = DataTypeSize::get_size(DataTypeSize::Word);
+const NIL: usize = usize::MAX;
+
+/* ---------------- recency order over slots ---------------- */
+/* doubly linked list threaded through slot numbers, MRU at the head;
+ * touch and victim lookup are O(1) whatever the cache size */
+#[derive(Debug)]
+struct RecencyList {
+    prev: Vec<usize>,
+    next: Vec<usize>,
+    head: usize,
+    tail: usize,
+}
+
+impl RecencyList {
+    fn new(slots: usize) -> Self {
+        Self { prev: vec![NIL; slots], next: vec![NIL; slots], head: NIL, tail: NIL }
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (p, n) = (self.prev[slot], self.next[slot]);
+        if p != NIL { self.next[p] = n; } else if self.head == slot { self.head = n; }
+        if n != NIL { self.prev[n] = p; } else if self.tail == slot { self.tail = p; }
+        self.prev[slot] = NIL;
+        self.next[slot] = NIL;
+    }
+
+    /* move (or insert) `slot` to the MRU end */
+    fn touch(&mut self, slot: usize) {
+        self.unlink(slot);
+        self.next[slot] = self.head;
+        if self.head != NIL { self.prev[self.head] = slot; }
+        self.head = slot;
+        if self.tail == NIL { self.tail = slot; }
+    }
+
+    fn lru(&self) -> Option<usize> {
+        (self.tail != NIL).then_some(self.tail)
+    }
+}
+
+#[derive(Debug)]
+pub struct FAssocCache {
+    lines: Vec<CacheLine>,
+
+    /* tag -> slot, so a lookup never scans the lines */
+    slots: HashMap<usize, usize>,
+
+    /* replacement state */
+    recency:      RecencyList,
+    free:         Vec<usize>,       // invalid slots, lowest on top
+    unreferenced: BTreeSet<usize>,  // NRU: filled but not touched since
+    clock:        u64,              // logical time, advanced on every reference
+    plru:         TreePlru,         // PLRU: one bit tree over every slot
+    oracle:       Option<NextUse>,  // OPT: future of the trace
+    rng:          StdRng,
+    next_victim:  usize,            // the next Random victim
+
+    words_per_line: usize,
+
+    /* stats */
+    eviction: EvictionPolicy,
+    pub(crate) stats: MemStats,
+}
+
+impl FAssocCache {
+    pub fn new(bytes: usize, words_per_line: usize, eviction: EvictionPolicy) -> Self {
+        assert!(bytes.is_power_of_two() && words_per_line.is_power_of_two());
+        let num_lines = bytes / (WORDSIZE * words_per_line);
+        assert!(num_lines > 0, "cache must hold ≥ 1 line");
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let next_victim = rng.random_range(0..num_lines);
+
+        Self {
+            lines: vec![CacheLine::new(words_per_line); num_lines],
+            slots: HashMap::with_capacity(num_lines),
+            recency: RecencyList::new(num_lines),
+            free: (0..num_lines).rev().collect(),
+            unreferenced: BTreeSet::new(),
+            clock: 0,
+            plru: TreePlru::new(1, num_lines),
+            oracle: None,
+            rng,
+            next_victim,
+            words_per_line,
+            eviction,
+            stats: MemStats::new(),
+        }
+    }
+
+    /// Reseed the RNG behind `EvictionPolicy::Random`.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.next_victim = self.rng.random_range(0..self.lines.len());
+        self
+    }
+
+    pub fn num_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn eviction(&self) -> EvictionPolicy { self.eviction }
+
+    pub fn set_oracle(&mut self, oracle: NextUse) {
+        self.oracle = Some(oracle);
+    }
+
+    pub(crate) fn line_mut(&mut self, addr: usize) -> Option<&mut CacheLine> {
+        self.find_line(addr).map(|slot| &mut self.lines[slot])
+    }
+
+    /* ---------------- lookup ---------------- */
+    fn find_line(&self, addr: usize) -> Option<usize> {
+        self.slots.get(&self.get_tag(addr)).copied()
+    }
+
+    /* ---------------- victim policy ------------------ */
+    fn victim_slot(&self) -> usize {
+        let lru = self.recency.lru().expect("victim requested from an empty cache");
+        match self.eviction {
+            EvictionPolicy::Lru => lru,
+
+            /* -------- NRU: lowest slot not referenced since its fill --- */
+            EvictionPolicy::Nru => self.unreferenced.first().copied().unwrap_or(lru),
+
+            /* -------- Random: pre-drawn, uniform over every slot ------- */
+            EvictionPolicy::Random => self.next_victim,
+
+            /* -------- PLRU: follow the bit tree ------------------------ */
+            EvictionPolicy::Plru => self.plru.victim(0),
+
+            /* RRIP needs sets to duel over; validation rejects it here */
+            EvictionPolicy::Srrip | EvictionPolicy::Brrip | EvictionPolicy::Drrip => lru,
+
+            /* -------- OPT: line next used furthest in the future ------- */
+            EvictionPolicy::Opt => {
+                let oracle = self.oracle.as_ref().expect("OPT eviction needs the trace (Memory::set_future)");
+                (0..self.lines.len())
+                    .max_by_key(|&slot| (oracle.next_use(self.base_addr(self.lines[slot].tag())), std::cmp::Reverse(slot)))
+                    .unwrap()
+            }
+        }
+    }
+
+    /* slot the next fill lands in: free slot first, then victim */
+    fn fill_slot(&self) -> usize {
+        self.free.last().copied().unwrap_or_else(|| self.victim_slot())
+    }
+
+    /* ---------------- hit / miss + bookkeeping ------- */
+    fn lookup(&mut self, addr: usize, op: Access, dont_count: bool) -> Option<usize> {
+        let slot = match self.find_line(addr) {
+            /* the re-access right after a fill: touching it would mark an
+             * untouched line referenced for NRU */
+            Some(hit) if dont_count => return Some(hit),
+            Some(hit) => {
+                self.stats.record_hit(op);
+                hit
+            }
+            None      => {
+                self.stats.record_miss(op);
+                return None;
+            }
+        };
+
+        self.touch(slot);
+        Some(slot)
+    }
+
+    fn touch(&mut self, slot: usize) {
+        self.recency.touch(slot);
+        self.unreferenced.remove(&slot);
+        self.clock += 1;
+        self.lines[slot].stamp(self.clock);
+        if matches!(self.eviction, EvictionPolicy::Plru) {
+            self.plru.touch(0, slot);
+        }
+    }
+
+    #[inline(always)]
+    fn base_addr(&self, tag: usize) -> usize {
+        tag << (self.word_bits() + self.byte_bits())
+    }
+}
+
+/* ===================================================================== */
+/* ================          CacheAccess impl             ============== */
+/* ===================================================================== */
+
+impl CacheAccess for FAssocCache {
+    fn read(&mut self, addr: usize, size: DataTypeSize, dont_count: bool)
+        -> Lookup<DataType>
+    {
+        /* ---------- hit / miss ---------- */
+        let Some(slot) = self.lookup(addr, Access::Read, dont_count) else { return Lookup::Miss };
+        self.stats.record_read(size.clone().size());
+
+        /* ---------- extract bytes ---------- */
+        let (_, _, word, byte) = self.decode_addr(addr);
+        let line  = &self.lines[slot];
+        let base  = word * WORDSIZE + byte;
+        let read  = |i| line.read_byte(base + i);
+
+        Lookup::Hit(match size {
+            DataTypeSize::Byte       => DataType::Byte(read(0)),
+            DataTypeSize::Halfword   => DataType::Halfword(u16::from_le_bytes([read(0), read(1)])),
+            DataTypeSize::Word       => DataType::Word(u32::from_le_bytes([read(0), read(1), read(2), read(3)])),
+            DataTypeSize::DoubleWord => DataType::DoubleWord(u64::from_le_bytes(std::array::from_fn(read))),
+        })
+    }
+
+    fn write(&mut self, data: DataType, addr: usize, dont_count: bool) -> Lookup<()> {
+        let Some(slot) = self.lookup(addr, Access::Write, dont_count) else { return Lookup::Miss };
+        self.stats.record_write(data.payload_size());
+
+        let (_, _, word, byte) = self.decode_addr(addr);
+        let offset = word * WORDSIZE + byte;
+        let line   = &mut self.lines[slot];
+
+        match data {
+            DataType::Byte(b)       => line.write_byte(offset, b),
+            DataType::Halfword(h)   => for (i, &b) in h.to_le_bytes().iter().enumerate() { line.write_byte(offset + i, b) },
+            DataType::Word(w)       => for (i, &b) in w.to_le_bytes().iter().enumerate() { line.write_byte(offset + i, b) },
+            DataType::DoubleWord(d) => for (i, &b) in d.to_le_bytes().iter().enumerate() { line.write_byte(offset + i, b) },
+        }
+        Lookup::Hit(())
+    }
+
+    fn stats(&self) -> &MemStats { &self.stats }
+}
+
+/* ===================================================================== */
+/* ==============     MemLevelAccess & CacheAddressing     ============= */
+/* ===================================================================== */
+
+impl MemLevelAccess for FAssocCache {
+    fn write_line(&mut self, addr: usize, _wpl: usize, data: Vec<u8>) {
+        let tag  = self.get_tag(addr);
+        let slot = self.fill_slot();
+        let line_bytes = self.words_per_line * WORDSIZE;
+
+        if self.free.last() == Some(&slot) {
+            self.free.pop();
+        } else {
+            self.stats.record_eviction(self.lines[slot].is_dirty(), line_bytes);
+            self.slots.remove(&self.lines[slot].tag());
+            self.next_victim = self.rng.random_range(0..self.lines.len());
+        }
+
+        self.stats.record_fill(line_bytes);
+        self.lines[slot].write_line(tag, data);
+        self.slots.insert(tag, slot);
+        self.recency.touch(slot);
+
+        if matches!(self.eviction, EvictionPolicy::Plru) {
+            self.plru.touch(0, slot);
+        }
+
+        /* NRU: a fresh line starts out unreferenced */
+        if matches!(self.eviction, EvictionPolicy::Nru) {
+            self.unreferenced.insert(slot);
+        }
+    }
+
+    fn fetch_line(&mut self, addr: usize, wpl: usize, dont_count: bool) -> Lookup<Vec<u8>> {
+        let Some(slot) = self.lookup(addr, Access::Read, dont_count) else { return Lookup::Miss };
+        self.stats.record_read(wpl * WORDSIZE);
+        let word = self.get_word_offset(addr);
+        Lookup::Hit(self.lines[slot].read_bytes(word * WORDSIZE, wpl * WORDSIZE))
+    }
+
+    fn writeback_line(&mut self, addr: usize, wpl: usize, data: Vec<u8>, dont_count: bool) -> Lookup<()> {
+        let Some(slot) = self.lookup(addr, Access::Write, dont_count) else { return Lookup::Miss };
+        self.stats.record_writeback(wpl * WORDSIZE);
+        let word = self.get_word_offset(addr);
+        self.lines[slot].write_bytes(word * WORDSIZE, &data[..wpl * WORDSIZE]);
+        Lookup::Hit(())
+    }
+}
+
+/* ---------------- CacheAddressing helpers ---------------- */
+
+impl CacheAddressing for FAssocCache {
+    #[inline] fn byte_bits (&self) -> usize { WORDSIZE.trailing_zeros() as usize }
+    #[inline] fn word_bits (&self) -> usize { self.words_per_line.trailing_zeros() as usize }
+    #[inline] fn index_bits(&self) -> usize { 0 }
+    #[inline] fn words_per_line(&self) -> usize { self.words_per_line }
+
+    fn decode_addr(&self, a: usize) -> (usize, usize, usize, usize) {
+        let bb = self.byte_bits();
+        let wb = self.word_bits();
+
+        let byte = a & ((1 << bb) - 1);
+        let word = (a >> bb) & ((1 << wb) - 1);
+        let tag  = a >> (bb + wb);
+
+        (tag, 0, word, byte)
+    }
+
+    /* on a miss this is the line that the next fill of `a` replaces */
+    fn is_line_dirty(&self, a: usize) -> bool {
+        let slot = self.find_line(a).unwrap_or_else(|| self.fill_slot());
+        let line = &self.lines[slot];
+        line.is_valid() && line.is_dirty()
+    }
+
+    fn get_evict_line_data(&self, _a: usize) -> Vec<u8> {
+        self.lines[self.fill_slot()].get_data()
+    }
+
+    fn get_writeback_addr(&self, _a: usize) -> usize {
+        self.base_addr(self.lines[self.fill_slot()].tag())
+    }
+
+    fn get_base_addr(&self, a: usize) -> usize {
+        self.base_addr(self.get_tag(a))
+    }
+
+    #[inline] fn get_tag        (&self, a: usize) -> usize { let (t, ..) = self.decode_addr(a); t }
+    #[inline] fn get_index      (&self, _a: usize) -> usize { 0 }
+    #[inline] fn get_word_offset(&self, a: usize) -> usize { let (_, _, w, ..) = self.decode_addr(a); w }
+    #[inline] fn get_byte_offset(&self, a: usize) -> usize { let (_, _, _, b) = self.decode_addr(a); b }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const L1_SIZE: usize = 1 << 10;
+    const WORD_P_LINE: usize = 4;
+    const LINE_BYTES: usize = WORDSIZE * WORD_P_LINE;
+
+    fn fill(c: &mut FAssocCache, addr: usize) {
+        let base = c.get_base_addr(addr);
+        c.write_line(base, WORD_P_LINE, vec![0; LINE_BYTES]);
+    }
+
+    #[test]
+    fn parse_addr() {
+        let c = FAssocCache::new(L1_SIZE, 8, EvictionPolicy::Lru);
+
+        let addr = 0x385;
+        assert_eq!(c.get_tag(addr), 0x1c);
+        assert_eq!(c.get_index(addr), 0);
+        assert_eq!(c.get_word_offset(addr), 0x1);
+        assert_eq!(c.get_byte_offset(addr), 0x1);
+        assert_eq!(c.get_base_addr(addr), 0x380);
+    }
+
+    #[test]
+    fn compulsory_miss() {
+        let mut c = FAssocCache::new(L1_SIZE, WORD_P_LINE, EvictionPolicy::Lru);
+
+        let result = c.read(0x385, DataTypeSize::Byte, false);
+        assert!(matches!(result, Lookup::Miss), "expected a miss, got: {result:?}");
+        assert_eq!(c.stats.total_accesses(), 1);
+    }
+
+    #[test]
+    fn single_write() {
+        let mut c = FAssocCache::new(L1_SIZE, WORD_P_LINE, EvictionPolicy::Lru);
+
+        let addr = 0x384;
+        fill(&mut c, addr);
+
+        let _ = c.write(DataType::Word(0xcafebabe), addr, false);
+        match c.read(addr, DataTypeSize::Word, false) {
+            Lookup::Hit(DataType::Word(d)) => assert_eq!(d, 0xcafebabe),
+            _ => panic!("Incorrect Read")
+        }
+        assert!(c.is_line_dirty(addr));
+
+        assert_eq!(c.stats.total_accesses(), 2);
+        assert_eq!(c.stats.hit_rate(), 1.0);
+    }
+
+    #[test]
+    fn no_conflict_misses() {
+        /* every line maps to index 0 of a direct-mapped cache this size */
+        let mut c = FAssocCache::new(L1_SIZE, WORD_P_LINE, EvictionPolicy::Lru);
+        let lines = c.num_lines();
+
+        for i in 0..lines { fill(&mut c, i * L1_SIZE); }
+        for i in 0..lines {
+            assert!(c.read(i * L1_SIZE, DataTypeSize::Word, false).is_hit(), "line {i} evicted");
+        }
+        assert_eq!(c.stats.hit_rate(), 1.0);
+    }
+
+    #[test]
+    fn lru_evicts_least_recent() {
+        let mut c = FAssocCache::new(4 * LINE_BYTES, WORD_P_LINE, EvictionPolicy::Lru);
+
+        for i in 0..4 { fill(&mut c, i * LINE_BYTES); }
+        let _ = c.read(0, DataTypeSize::Word, false);           // line 1 is now LRU
+
+        assert_eq!(c.get_writeback_addr(4 * LINE_BYTES), LINE_BYTES);
+        fill(&mut c, 4 * LINE_BYTES);
+        assert!(c.read(LINE_BYTES, DataTypeSize::Word, false).is_miss());
+        assert!(c.read(0, DataTypeSize::Word, false).is_hit());
+    }
+
+    #[test]
+    fn nru_prefers_unreferenced() {
+        let mut c = FAssocCache::new(4 * LINE_BYTES, WORD_P_LINE, EvictionPolicy::Nru);
+
+        for i in 0..4 { fill(&mut c, i * LINE_BYTES); }
+        let _ = c.read(0, DataTypeSize::Word, false);
+        let _ = c.read(2 * LINE_BYTES, DataTypeSize::Word, false);
+
+        /* slots 1 and 3 were never referenced; the lowest goes first */
+        assert_eq!(c.get_writeback_addr(4 * LINE_BYTES), LINE_BYTES);
+    }
+
+    #[test]
+    fn dirty_victim_written_back() {
+        let mut c = FAssocCache::new(2 * LINE_BYTES, WORD_P_LINE, EvictionPolicy::Lru);
+
+        fill(&mut c, 0);
+        fill(&mut c, LINE_BYTES);
+        let _ = c.write(DataType::Word(0x1234), 0, false);
+        let _ = c.read(LINE_BYTES, DataTypeSize::Word, false);  // line 0 is LRU and dirty
+
+        let next = 2 * LINE_BYTES;
+        assert!(c.is_line_dirty(next));
+        assert_eq!(c.get_writeback_addr(next), 0);
+        assert_eq!(&c.get_evict_line_data(next)[..4], &0x1234u32.to_le_bytes());
+    }
+
+    #[test]
+    fn random_victims_follow_seed() {
+        /* writeback address the next fill would evict, after each of 32 fills */
+        let victims = |seed: u64| -> Vec<usize> {
+            let mut c = FAssocCache::new(8 * LINE_BYTES, WORD_P_LINE, EvictionPolicy::Random).with_seed(seed);
+            (0..8).for_each(|i| fill(&mut c, i * LINE_BYTES));
+            (8..40).map(|i| {
+                let victim = c.get_writeback_addr(i * LINE_BYTES);
+                fill(&mut c, i * LINE_BYTES);
+                victim
+            }).collect()
+        };
+
+        assert_eq!(victims(3), victims(3), "same seed, same victims");
+        assert_ne!(victims(3), victims(4), "different seed, different victims");
+    }
+}
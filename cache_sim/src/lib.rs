@@ -0,0 +1,56 @@
+//! A trace-driven cache hierarchy simulator.
+//!
+//! A [`Memory`] is built from a [`MemoryConfig`]: split L1 instruction and
+//! data caches, optional shared L2 and L3, an optional write buffer and main
+//! memory. Accesses go in through [`MemoryAccess`]; every level keeps its own
+//! [`MemStats`], and [`Memory::report`] gathers them for printing or export.
+//!
+//! ```
+//! use cache_sim::{CacheConfig, DataType, DataTypeSize, EvictionPolicy, Memory, MemoryAccess, MemoryConfig, Routing};
+//!
+//! let config = MemoryConfig {
+//!     l2: Some(CacheConfig::set_associative(1 << 16, 8, 8, EvictionPolicy::Lru)),
+//!     routing: Some(Routing::Kind),   // fetches to l1i, loads and stores to l1d
+//!     ..MemoryConfig::default()
+//! };
+//! let mut mem = Memory::new(&config).unwrap();
+//!
+//! mem.write(DataType::Word(0xcafe), 0x1000, false).unwrap();
+//! assert_eq!(mem.read(0x1000, DataTypeSize::Word, false).unwrap(), DataType::Word(0xcafe));
+//! mem.fetch(0x40, DataTypeSize::Word, false).unwrap();
+//!
+//! let report = mem.report();
+//! let l1d = report.levels.iter().find(|level| level.level == "l1d").unwrap();
+//! assert_eq!((l1d.stats.misses(), l1d.stats.hits()), (1, 1));   // the write filled the line
+//! ```
+//!
+//! Traces in any of the supported formats are read with [`trace::open`],
+//! which yields [`trace::TraceRecord`]s to feed the hierarchy.
+
+pub mod cache;
+pub mod config;
+pub mod direct_map;
+pub mod ffi;
+pub mod fully_associative;
+pub mod main_memory;
+pub mod mem_stats;
+pub mod memory;
+pub mod report;
+pub mod set_associative;
+pub mod three_c;
+pub mod trace;
+pub mod write_buffer;
+
+mod opt;
+mod plru;
+mod rrip;
+
+pub use cache::{Cache, CacheConfig, EvictionPolicy, WriteHit, WriteMiss};
+pub use config::ConfigError;
+pub use mem_stats::MemStats;
+pub use memory::{
+    AccessKind, CacheAccess, DataType, DataTypeSize, Lookup, Memory, MemoryAccess, MemoryConfig,
+    MemoryError, MemoryErrorKind, Routing,
+};
+pub use report::Report;
+pub use write_buffer::{DrainPolicy, WriteBufferConfig};
@@ -1,103 +1,137 @@
-use std::io::{BufReader, BufRead, Result};
+use std::io::{self, BufWriter, Result, Write};
 use std::fs::File;
 
-mod mem_stats;
-mod memory;
-mod main_memory;
-mod cache;
-mod direct_map;
-mod set_associative;
+use clap::Parser;
 
-use crate::memory::{Memory, DataTypeSize, DataType, MemoryAccess};
+use cache_sim::{config, trace, EvictionPolicy, Memory, MemoryAccess, MemoryConfig};
+use cache_sim::trace::{BinaryWriter, Skipped, TraceError, TraceOp};
 
-// const TRACE_FILE: &str = "mem_files/big_minecraft_log2.txt";
-const TRACE_FILE: &str = "mem_files/big_flappy_log2.txt";
-// const TRACE_FILE: &str = "mem_files/med_flappy.txt";
-// const TRACE_FILE: &str = "mem_files/small_flappy.txt";
+mod cli;
 
-/* ── compile-time cache geometry ─────────────────────────────────────── */
-const FULL_BYTES         : usize = 1 << 22;   // 4 MiB main memory
-const IM_L1_BYTES        : usize = 1 << 14;   // 16 KiB I-cache
-const IM_L1_WORDS_PER_LN : usize = 8;         // 8 words / line
-const DM_L1_BYTES        : usize = 1 << 13;   // 8 KiB D-cache
-const DM_L1_WORDS_PER_LN : usize = 4;         // 4 words / line
+use crate::cli::{Args, OutputFormat};
 
-type SimMem = Memory<
-    FULL_BYTES,
-    IM_L1_BYTES, IM_L1_WORDS_PER_LN,
-    DM_L1_BYTES, DM_L1_WORDS_PER_LN,
-    2, 2
->;
+fn fail(e: impl std::fmt::Display) -> ! {
+    eprintln!("cache_sim: {e}");
+    std::process::exit(2);
+}
 
-const DM_BASE  : usize = 0x0060_0000;  // start of data region
-const MMIO_BASE: usize = 0xA000_0000;  // start of MMIO region
+/* bad records: strict stops at the first, lenient skips and counts them and
+ * shows the first few as they come; a trace that cannot be read stops both */
+const SHOWN_ERRORS: usize = 10;
 
-/* ─────────────────────────────────────────────────────────────────────── */
+struct BadRecords {
+    strict: bool,
+    skipped: Skipped,
+}
+
+impl BadRecords {
+    fn handle(&mut self, e: TraceError) {
+        if self.strict || e.is_fatal() {
+            fail(e);
+        }
+        if self.skipped.total() < SHOWN_ERRORS {
+            eprintln!("cache_sim: {e}");
+        }
+        self.skipped.count(&e);
+    }
+
+    fn report(&self) {
+        if self.skipped.total() > 0 {
+            eprintln!("cache_sim: {}", self.skipped);
+        }
+    }
+}
 
 fn main() -> Result<()> {
-    let reader = BufReader::new(File::open(TRACE_FILE)?);
+    let args = Args::parse();
+
+    let mut config = match &args.config {
+        Some(path) => config::load(path).unwrap_or_else(|e| fail(e)),
+        None       => MemoryConfig::default(),
+    };
+    args.apply(&mut config).unwrap_or_else(|e| fail(e));
+
+    let mut bad = BadRecords { strict: args.strict, skipped: Skipped::default() };
+
+    if let Some(path) = &args.convert {
+        let mut out = BinaryWriter::new(BufWriter::new(File::create(path)?))?;
+        let mut counter = 0;
+        for record in trace::open(&args.trace, args.trace_format)? {
+            match record {
+                Ok(record) => { out.write(&record)?; counter += 1; }
+                Err(e)     => bad.handle(e),
+            }
+        }
+        out.finish()?;
+        bad.report();
+        println!("Converted {counter} records to {}", path.display());
+        return Ok(());
+    }
 
-    let mut mem = SimMem::new(MMIO_BASE, DM_BASE);
+    /* without a routing from the config or flags, the trace picks one */
+    let mut records = trace::open(&args.trace, args.trace_format)?;
+    config.routing.get_or_insert(records.routing());
+    let mut mem = Memory::new(&config).unwrap_or_else(|e| fail(e));
+
+    /* OPT looks ahead: hand it every address the loop below will issue */
+    if config.uses_policy(EvictionPolicy::Opt) {
+        if trace::is_stdin(&args.trace) {
+            fail("policy opt reads the trace twice and cannot take it from stdin");
+        }
+        let future: Vec<usize> = trace::open(&args.trace, args.trace_format)?
+            .filter_map(|record| record.ok())
+            .map(|record| record.addr)
+            .collect();
+        mem.set_future(&future);
+    }
 
     let mut counter = 0;
-    for (line_no, line) in reader.lines().flatten().enumerate() {
-        if line.trim().is_empty() { continue; }
-
-        let cols: Vec<&str> = line.split_ascii_whitespace().collect();
-        let op   = cols[0].chars().next().unwrap();
-        let addr = usize::from_str_radix(cols[1], 16)
-            .expect("address must be hex");
-        let sz_b = cols[2].parse::<usize>()
-            .expect("size must be decimal (1/2/4/8)");
-
-        /* map byte-count → enum */
-        let size_enum = match sz_b {
-            1 => DataTypeSize::Byte,
-            2 => DataTypeSize::Halfword,
-            4 => DataTypeSize::Word,
-            8 => DataTypeSize::DoubleWord,
-            _ => { eprintln!("L{line_no}: unsupported size {sz_b}"); continue; }
+    while let Some(record) = records.next() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e)     => { bad.handle(e); continue; }
         };
+        let addr = record.addr;
+
+        let result = match record.op {
+            /* ---------------- FETCH --------------- */
+            TraceOp::Fetch(size) => mem.fetch(addr, size, false).map(drop),
 
-        counter += 1;
-        match op {
             /* ---------------- READ ---------------- */
-            'r' => {
-                let val = mem.read(addr, size_enum, false)
-                    .unwrap_or_else(|e| panic!("L{line_no}: {e:?}"));
-                // println!("read {sz_b}B @ 0x{addr:x} → {:?}", val);
-            }
+            TraceOp::Read(size)  => mem.read(addr, size, false).map(drop),
 
             /* ---------------- WRITE --------------- */
-            'w' => {
-                if cols.len() != 4 {
-                    eprintln!("L{line_no}: write line needs a value");
-                    continue;
-                }
-                let raw = cols[3].parse::<u64>()
-                    .expect("value must be decimal");
-
-                let data = match sz_b {
-                    1 => DataType::Byte(raw as u8),
-                    2 => DataType::Halfword(raw as u16),
-                    4 => DataType::Word(raw as u32),
-                    8 => DataType::DoubleWord(raw),
-                    _ => unreachable!(),
-                };
-
-                mem.write(data, addr, false)
-                    .unwrap_or_else(|e| panic!("L{line_no}: {e:?}"));
-                // println!("write {:?} @ 0x{addr:x}", data);
-            }
-
-            _ => eprintln!("L{line_no}: unknown op '{op}'"),
+            TraceOp::Write(data) => mem.write(data, addr, false),
+        };
+        match result {
+            Ok(())  => counter += 1,
+            Err(e)  => bad.handle(TraceError::access(records.pos(), e)),
         }
     }
+    bad.report();
+
+    /* whatever the write buffer still holds reaches memory at the end */
+    mem.drain_write_buffer();
 
-    /* optional: show cache & memory statistics */
-    mem.print_summary();
-    
-    println!("Completed {counter} operations");
+    match args.format {
+        OutputFormat::Text => {
+            mem.print_summary();
+            println!("Completed {counter} operations");
+        }
+        OutputFormat::Csv | OutputFormat::Json => {
+            let mut out: Box<dyn Write> = match &args.output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None       => Box::new(io::stdout().lock()),
+            };
+            let report = mem.report();
+            if args.format == OutputFormat::Csv {
+                report.write_csv(&mut out)?;
+            } else {
+                report.write_json(&mut out)?;
+            }
+            out.flush()?;
+        }
+    }
     Ok(())
 }
 
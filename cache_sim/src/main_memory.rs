@@ -1,164 +1,163 @@
-use crate::{mem_stats::MemStats, memory::*};
+use std::collections::HashMap;
+
+use crate::{mem_stats::{Access, MemStats}, memory::*};
 
 const WORDSIZE: usize = DataTypeSize::get_size(DataTypeSize::Word);
+const PAGE_BYTES: usize = 1 << 12;
 
+/* backed sparsely, a page at a time on first store: a trace of a 64-bit
+ * process touches a few MiB spread over most of the address space, and
+ * bytes never stored read as zero */
 #[derive(Debug)]
-pub struct MainMemory<
-    const BYTES: usize
-> {
-    data: Vec<u8>,
+pub struct MainMemory {
+    pages: HashMap<usize, Box<[u8; PAGE_BYTES]>>,   // page number -> contents
+    size: usize,
     stats: MemStats,
 }
 
-impl<const BYTES: usize> MainMemory<BYTES> {
-    pub fn new() -> Self {
-        MainMemory { data: vec![0; BYTES], stats: MemStats::new()}
+impl MainMemory {
+    pub fn new(bytes: usize) -> Self {
+        MainMemory { pages: HashMap::new(), size: bytes, stats: MemStats::new()}
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn record_cycles(&mut self, cycles: u64) {
+        self.stats.record_cycles(cycles);
+    }
+
+    /* a retired write buffer entry: only the bytes set in mask are written,
+     * counted as one writeback; unbacked bytes are dropped */
+    pub fn write_masked(&mut self, base_addr: usize, data: &[u8], mask: &[bool]) {
+        let mut n_bytes = 0;
+        for (i, (&byte, &set)) in data.iter().zip(mask).enumerate() {
+            if set && base_addr + i < self.size() {
+                self.write_bytes(base_addr + i, &[byte]);
+            }
+            n_bytes += set as usize;
+        }
+        self.stats.record_hit(Access::Write);
+        self.stats.record_writeback(n_bytes);
     }
 }
 
-impl<const BYTES: usize> MemLevelAccess for MainMemory<BYTES> {
+impl MemLevelAccess for MainMemory {
     fn write_line(&mut self, base_addr: usize, words_per_lines: usize, data: Vec<u8>) {
         let n_bytes: usize = words_per_lines * WORDSIZE; 
 
-        self.data[base_addr..(n_bytes + base_addr)].copy_from_slice(&data[..n_bytes]);
+        /* accesses are bounds checked before they reach a cache, so only a
+         * line straddling the end of a memory that is not a whole number of
+         * lines gets here; the unbacked part is not stored */
+        if !self.backs(base_addr, n_bytes) { return; }
+        self.write_bytes(base_addr, &data[..n_bytes]);
     }
 
-    fn fetch_line(&self, base_addr: usize, words_per_lines: usize) -> Vec<u8> {
+    /* main memory never misses; a line past the end (see write_line) reads as zero */
+    fn fetch_line(&mut self, base_addr: usize, words_per_lines: usize, _: bool) -> Lookup<Vec<u8>> {
         let n_bytes: usize = words_per_lines * WORDSIZE; 
         let mut ret_vec: Vec<u8> = vec![0; n_bytes];
+        self.stats.record_hit(Access::Read);
+        self.stats.record_read(n_bytes);
     
-        ret_vec[..n_bytes].copy_from_slice(&self.data[base_addr..(n_bytes + base_addr)]);
-        ret_vec
+        if self.backs(base_addr, n_bytes) {
+            self.read_bytes(base_addr, &mut ret_vec);
+        }
+        Lookup::Hit(ret_vec)
+    }
+
+    fn writeback_line(&mut self, base_addr: usize, words_per_lines: usize, data: Vec<u8>, _: bool) -> Lookup<()> {
+        self.stats.record_hit(Access::Write);
+        self.stats.record_writeback(words_per_lines * WORDSIZE);
+        self.write_line(base_addr, words_per_lines, data);
+        Lookup::Hit(())
     }
 }
 
-impl<const BYTES: usize> MemoryAccess for MainMemory<BYTES> {
+impl MemoryAccess for MainMemory {
     fn read(&mut self, addr: usize, size: DataTypeSize, _: bool) -> Result<DataType, MemoryError> {
-        if addr >= BYTES {
-            return Err(MemoryError::OutOfBounds);
-        }
+        let data = self.load(addr, size)?;
+        self.stats.record_hit(Access::Read);
+        self.stats.record_read(data.payload_size());
+        Ok(data)
+    }
 
-        match size {
-            DataTypeSize::Byte => {
-                let byte = self.data[addr];
-                // println!("[MAIN MEMORY] reading {} at {:x}", byte, addr);
-                Ok(DataType::Byte(byte))
-            }
+    fn write(&mut self, data: DataType, addr: usize, _: bool) -> Result<(), MemoryError> {
+        self.store(data, addr)?;
+        self.stats.record_hit(Access::Write);
+        self.stats.record_write(data.payload_size());
+        Ok(())
+    }
 
-            DataTypeSize::Halfword => {
-                if addr % 2 != 0 {
-                    return Err(MemoryError::NotAligned);
-                }
-                if addr + 1 >= BYTES {
-                    return Err(MemoryError::OutOfBounds);
-                }
-                let val = u16::from_le_bytes([
-                    self.data[addr], 
-                    self.data[addr + 1]
-                ]);
-                Ok(DataType::Halfword(val))
-            }
+    fn stats(&self) -> &MemStats {
+        &self.stats
+    }
+}
 
-            DataTypeSize::Word => {
-                if addr % 4 != 0 {
-                    return Err(MemoryError::NotAligned);
-                }
-                if addr + 3 >= BYTES {
-                    return Err(MemoryError::OutOfBounds);
-                }
-                let val = u32::from_le_bytes([
-                    self.data[addr],
-                    self.data[addr + 1],
-                    self.data[addr + 2],
-                    self.data[addr + 3],
-                ]);
-
-                // println!("[MAIN MEMORY] reading {} at {:x}", val, addr);
-                Ok(DataType::Word(val))
-            }
+impl MainMemory {
+    /* n bytes at addr all lie inside memory */
+    fn backs(&self, addr: usize, n: usize) -> bool {
+        addr.checked_add(n).is_some_and(|end| end <= self.size())
+    }
+
+    /// An access of n bytes at addr lies inside memory and is aligned.
+    pub fn check(&self, addr: usize, n: usize) -> Result<(), MemoryError> {
+        let kind = if addr >= self.size() {
+            MemoryErrorKind::OutOfBounds
+        } else if !addr.is_multiple_of(n) {
+            MemoryErrorKind::NotAligned
+        } else if !self.backs(addr, n) {
+            MemoryErrorKind::OutOfBounds
+        } else {
+            return Ok(());
+        };
+        Err(MemoryError::new(kind, addr, n, "main"))
+    }
 
-            DataTypeSize::DoubleWord => {
-                if addr % 8 != 0 {
-                    return Err(MemoryError::NotAligned);
-                }
-                if addr + 7 >= BYTES {
-                    return Err(MemoryError::OutOfBounds);
-                }
-                let val = u64::from_le_bytes([
-                    self.data[addr],
-                    self.data[addr + 1],
-                    self.data[addr + 2],
-                    self.data[addr + 3],
-                    self.data[addr + 4],
-                    self.data[addr + 5],
-                    self.data[addr + 6],
-                    self.data[addr + 7],
-                ]);
-                Ok(DataType::DoubleWord(val))
+    /* buf.len() bytes from addr on; untouched pages read as zero */
+    fn read_bytes(&self, mut addr: usize, mut buf: &mut [u8]) {
+        while !buf.is_empty() {
+            let offset = addr % PAGE_BYTES;
+            let (chunk, rest) = buf.split_at_mut(buf.len().min(PAGE_BYTES - offset));
+            match self.pages.get(&(addr / PAGE_BYTES)) {
+                Some(page) => chunk.copy_from_slice(&page[offset..offset + chunk.len()]),
+                None       => chunk.fill(0),
             }
+            addr += chunk.len();
+            buf = rest;
         }
     }
 
-    fn write(&mut self, data: DataType, addr: usize, _: bool) -> Result<(), MemoryError> {
-        if addr >= BYTES {
-            return Err(MemoryError::OutOfBounds);
+    fn write_bytes(&mut self, mut addr: usize, mut data: &[u8]) {
+        while !data.is_empty() {
+            let offset = addr % PAGE_BYTES;
+            let (chunk, rest) = data.split_at(data.len().min(PAGE_BYTES - offset));
+            let page = self.pages.entry(addr / PAGE_BYTES).or_insert_with(|| Box::new([0; PAGE_BYTES]));
+            page[offset..offset + chunk.len()].copy_from_slice(chunk);
+            addr += chunk.len();
+            data = rest;
         }
+    }
 
-        match data {
-            DataType::Byte(val) => {
-                self.data[addr] = val;
-                Ok(())
-            }
-
-            DataType::Halfword(val) => {
-                if addr % 4 != 0 {
-                    return Err(MemoryError::NotAligned);
-                }
-                if addr + 3 >= BYTES {
-                    return Err(MemoryError::OutOfBounds);
-                }
-
-                let bytes = val.to_le_bytes();
-                self.data[addr..(DataTypeSize::get_size(DataTypeSize::Halfword) + addr)]
-                    .copy_from_slice(&bytes);
+    fn load(&self, addr: usize, size: DataTypeSize) -> Result<DataType, MemoryError> {
+        self.check(addr, DataTypeSize::get_size(size.clone()))?;
 
-                Ok(())
-            }
+        let mut bytes = [0; 8];
+        self.read_bytes(addr, &mut bytes[..size.clone().size()]);
+        let [b0, b1, b2, b3, ..] = bytes;
 
-            DataType::Word(val) => {
-                if addr % 4 != 0 {
-                    return Err(MemoryError::NotAligned);
-                }
-                if addr + 3 >= BYTES {
-                    return Err(MemoryError::OutOfBounds);
-                }
-                
-                let bytes = val.to_le_bytes();
-                self.data[addr..(DataTypeSize::get_size(DataTypeSize::Word) + addr)]
-                    .copy_from_slice(&bytes);
-
-                Ok(())
-            }
-
-            DataType::DoubleWord(val) => {
-                if addr % 4 != 0 {
-                    return Err(MemoryError::NotAligned);
-                }
-                if addr + 3 >= BYTES {
-                    return Err(MemoryError::OutOfBounds);
-                }
-                
-                let bytes = val.to_le_bytes();
-                self.data[addr..(DataTypeSize::get_size(DataTypeSize::DoubleWord) + addr)]
-                    .copy_from_slice(&bytes);
-
-                Ok(())
-            }
+        match size {
+            DataTypeSize::Byte       => Ok(DataType::Byte(b0)),
+            DataTypeSize::Halfword   => Ok(DataType::Halfword(u16::from_le_bytes([b0, b1]))),
+            DataTypeSize::Word       => Ok(DataType::Word(u32::from_le_bytes([b0, b1, b2, b3]))),
+            DataTypeSize::DoubleWord => Ok(DataType::DoubleWord(u64::from_le_bytes(bytes))),
         }
     }
 
-    fn stats(&self) -> &MemStats {
-        &self.stats
+    fn store(&mut self, data: DataType, addr: usize) -> Result<(), MemoryError> {
+        self.check(addr, data.payload_size())?;
+        self.write_bytes(addr, &data.to_le_bytes());
+        Ok(())
     }
-
-}
\ No newline at end of file
+}
@@ -1,28 +1,194 @@
-#[derive(Debug, Default)]
+use serde::Serialize;
+
+use crate::three_c::MissKind;
+
+/// Which way an access moves data: a load or line fetch, or a store or writeback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct MemStats {
-    hits: usize,
-    misses: usize,
+    read_hits: usize,
+    read_misses: usize,
+    write_hits: usize,
+    write_misses: usize,
+
+    /* 3C breakdown of misses, for the caches that classify them */
+    compulsory: usize,
+    capacity: usize,
+    conflict: usize,
+
+    /* write traffic received from the level above (stores, for an L1) */
+    writes: usize,          // word-sized writes: stores, write-through, write-around
+    writebacks: usize,      // dirty lines from the level above
+    bytes_written: usize,
+
+    /* line movement: fills in, victims out */
+    fills: usize,
+    clean_evictions: usize,
+    dirty_writebacks: usize,    // dirty victims this level sent down
+
+    /* every byte that landed in (fills, stores, writebacks) or left (loads,
+     * line fetches, dirty victims) this level */
+    bytes_in: usize,
+    bytes_out: usize,
+
+    /* latency: cycles summed over the accesses this level served, and how
+     * many those were (writebacks and posted stores are not timed) */
+    cycles: u64,
+    timed: usize,
 }
 
 impl MemStats {
     pub fn new() -> Self {
-        MemStats {
-            hits: 0,
-            misses: 0,
+        MemStats::default()
+    }
+
+    pub fn record_hit(&mut self, op: Access) {
+        match op {
+            Access::Read  => self.read_hits += 1,
+            Access::Write => self.write_hits += 1,
         }
     }
 
-    pub fn record_hit(&mut self) {
-        self.hits += 1;
+    pub fn record_miss(&mut self, op: Access) {
+        match op {
+            Access::Read  => self.read_misses += 1,
+            Access::Write => self.write_misses += 1,
+        }
+    }
+
+    /* data handed up to the level above by a load or line fetch */
+    pub fn record_read(&mut self, bytes: usize) {
+        self.bytes_out += bytes;
+    }
+
+    pub fn record_fill(&mut self, bytes: usize) {
+        self.fills += 1;
+        self.bytes_in += bytes;
     }
 
-    pub fn record_miss(&mut self) {
-        self.misses += 1;
+    /* a valid line replaced by a fill; a dirty one was written back first */
+    pub fn record_eviction(&mut self, dirty: bool, bytes: usize) {
+        if dirty {
+            self.dirty_writebacks += 1;
+            self.bytes_out += bytes;
+        } else {
+            self.clean_evictions += 1;
+        }
     }
 
+    pub fn record_miss_kind(&mut self, kind: MissKind) {
+        match kind {
+            MissKind::Compulsory => self.compulsory += 1,
+            MissKind::Capacity   => self.capacity += 1,
+            MissKind::Conflict   => self.conflict += 1,
+        }
+    }
+
+    pub fn record_cycles(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        self.timed += 1;
+    }
+
+    pub fn record_write(&mut self, bytes: usize) {
+        self.writes += 1;
+        self.bytes_written += bytes;
+        self.bytes_in += bytes;
+    }
+
+    pub fn record_writeback(&mut self, bytes: usize) {
+        self.writebacks += 1;
+        self.bytes_written += bytes;
+        self.bytes_in += bytes;
+    }
+
+    pub fn hits(&self) -> usize {
+        self.read_hits + self.write_hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.read_misses + self.write_misses
+    }
+
+    pub fn read_hits(&self) -> usize {
+        self.read_hits
+    }
+
+    pub fn read_misses(&self) -> usize {
+        self.read_misses
+    }
+
+    pub fn write_hits(&self) -> usize {
+        self.write_hits
+    }
+
+    pub fn write_misses(&self) -> usize {
+        self.write_misses
+    }
+
+    pub fn compulsory(&self) -> usize {
+        self.compulsory
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn conflict(&self) -> usize {
+        self.conflict
+    }
+
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    pub fn writebacks(&self) -> usize {
+        self.writebacks
+    }
+
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    pub fn fills(&self) -> usize {
+        self.fills
+    }
+
+    pub fn clean_evictions(&self) -> usize {
+        self.clean_evictions
+    }
+
+    pub fn dirty_writebacks(&self) -> usize {
+        self.dirty_writebacks
+    }
+
+    pub fn bytes_in(&self) -> usize {
+        self.bytes_in
+    }
+
+    pub fn bytes_out(&self) -> usize {
+        self.bytes_out
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Average access time in cycles of the accesses that were timed.
+    pub fn amat(&self) -> f64 {
+        if self.timed == 0 {
+            0.0
+        } else {
+            self.cycles as f64 / self.timed as f64
+        }
+    }
 
     pub fn total_accesses(&self) -> usize {
-        self.hits + self.misses
+        self.hits() + self.misses()
     }
 
     pub fn hit_rate(&self) -> f64 {
@@ -30,18 +196,48 @@ impl MemStats {
         if total == 0 {
             0.0
         } else {
-            self.hits as f64 / total as f64
+            self.hits() as f64 / total as f64
         }
     }
 
     pub fn miss_rate(&self) -> f64 {
-        1.0 - self.hit_rate()
+        let total = self.total_accesses();
+        if total == 0 {
+            0.0
+        } else {
+            self.misses() as f64 / total as f64
+        }
     }
 
     pub fn print_summary(&self) {
-        println!("\tHits: {}", self.hits);
-        println!("\tMisses: {}", self.misses);
+        println!("\tHits: {} ({} read, {} write)", self.hits(), self.read_hits, self.write_hits);
+        println!("\tMisses: {} ({} read, {} write)", self.misses(), self.read_misses, self.write_misses);
+        if self.compulsory + self.capacity + self.conflict > 0 {
+            println!("\t  {} compulsory, {} capacity, {} conflict", self.compulsory, self.capacity, self.conflict);
+        }
         println!("\tHit Rate: {:.2}%", self.hit_rate() * 100.0);
         println!("\tMiss Rate: {:.2}%", self.miss_rate() * 100.0);
+        println!("\tWrites In: {} ({} writebacks, {} bytes)", self.writes, self.writebacks, self.bytes_written);
+        println!("\tFills: {} ({} clean evictions, {} dirty writebacks)", self.fills, self.clean_evictions, self.dirty_writebacks);
+        println!("\tBytes: {} in, {} out", self.bytes_in, self.bytes_out);
+        if self.timed > 0 {
+            println!("\tAMAT: {:.2} cycles ({} cycles over {} accesses)", self.amat(), self.cycles, self.timed);
+        }
+    }
+
+    pub const CSV_HEADER: &'static str =
+        "level,accesses,hits,misses,hit_rate,miss_rate,writes,writebacks,bytes_written,\
+         read_hits,read_misses,write_hits,write_misses,fills,clean_evictions,dirty_writebacks,bytes_in,bytes_out,\
+         compulsory,capacity,conflict,cycles,amat";
+
+    pub fn csv_row(&self, level: &str) -> String {
+        format!(
+            "{level},{},{},{},{:.6},{:.6},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{:.6}",
+            self.total_accesses(), self.hits(), self.misses(), self.hit_rate(), self.miss_rate(),
+            self.writes, self.writebacks, self.bytes_written,
+            self.read_hits, self.read_misses, self.write_hits, self.write_misses,
+            self.fills, self.clean_evictions, self.dirty_writebacks, self.bytes_in, self.bytes_out,
+            self.compulsory, self.capacity, self.conflict, self.cycles, self.amat(),
+        )
     }
 }
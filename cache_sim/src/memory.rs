@@ -1,11 +1,18 @@
 use crate::mem_stats::*;
 use crate::main_memory::*;
 use crate::cache::*;
-use crate::direct_map::*;
-use crate::set_associative::*;
-// use crate::set_associative::SetAssocCache;
+use crate::config::ConfigError;
+use crate::opt::NextUse;
+use crate::report::{LevelReport, Report, WriteBufferReport};
+use crate::write_buffer::{WriteBuffer, WriteBufferConfig};
 
+use serde::{Deserialize, Serialize};
+
+use std::cell::Cell;
+use std::fmt;
 use std::mem;
+use std::rc::Rc;
+use std::str::FromStr;
 
 const WORDSIZE: usize = DataTypeSize::get_size(DataTypeSize::Word);
 
@@ -31,29 +38,131 @@ impl DataType {
             DataType::DoubleWord(_) => mem::size_of::<u64>(),
         }
     }
+
+    /* a `size` access that read all zero bytes */
+    fn zero(size: DataTypeSize) -> Self {
+        match size {
+            DataTypeSize::Byte       => DataType::Byte(0),
+            DataTypeSize::Halfword   => DataType::Halfword(0),
+            DataTypeSize::Word       => DataType::Word(0),
+            DataTypeSize::DoubleWord => DataType::DoubleWord(0),
+        }
+    }
+
+    pub fn to_le_bytes(self) -> Vec<u8> {
+        match self {
+            DataType::Byte(v)       => vec![v],
+            DataType::Halfword(v)   => v.to_le_bytes().to_vec(),
+            DataType::Word(v)       => v.to_le_bytes().to_vec(),
+            DataType::DoubleWord(v) => v.to_le_bytes().to_vec(),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub enum MemoryError {
+/// An access the hierarchy could not serve: what went wrong, the access,
+/// and the level that refused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryError {
+    pub kind: MemoryErrorKind,
+    pub addr: usize,
+    pub size: usize,            // bytes the access covered
+    pub level: &'static str,    // l1i, l1d or main, as named in the config
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryErrorKind {
+    /// runs past the end of main memory
     OutOfBounds,
+    /// not aligned to its own size
     NotAligned,
-    NotFound,
-    NotCompatible, 
 }
 
+impl MemoryError {
+    pub fn new(kind: MemoryErrorKind, addr: usize, size: usize, level: &'static str) -> Self {
+        Self { kind, addr, size, level }
+    }
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            MemoryErrorKind::OutOfBounds => "runs past the end of memory",
+            MemoryErrorKind::NotAligned  => "is misaligned",
+        };
+        write!(f, "{}: {}-byte access at {:#x} {what}", self.level, self.size, self.addr)
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+/// What a single cache level found. A miss is how a level hands an access
+/// to the one below, not an error, so it is kept out of `MemoryError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup<T> {
+    Hit(T),
+    Miss,
+}
+
+impl<T> Lookup<T> {
+    pub fn is_hit(&self) -> bool {
+        matches!(self, Lookup::Hit(_))
+    }
+
+    pub fn is_miss(&self) -> bool {
+        matches!(self, Lookup::Miss)
+    }
+
+    pub fn hit(self) -> Option<T> {
+        match self {
+            Lookup::Hit(v) => Some(v),
+            Lookup::Miss   => None,
+        }
+    }
+
+    /* for the re-access right after a fill, which cannot miss */
+    pub fn unwrap(self) -> T {
+        self.hit().expect("lookup missed")
+    }
+}
+
+/// What an access is for; under `Routing::Kind` it picks the L1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    IFetch,
+    Load,
+    Store,
+}
+
+/* a whole memory: every access is served or fails with a MemoryError */
 pub trait MemoryAccess {
     fn read(&mut self, addr: usize, size: DataTypeSize, dont_count: bool) -> Result<DataType, MemoryError>;
+    /* an instruction fetch; a level that does not tell code from data
+     * serves it as a read */
+    fn fetch(&mut self, addr: usize, size: DataTypeSize, dont_count: bool) -> Result<DataType, MemoryError> {
+        self.read(addr, size, dont_count)
+    }
     fn write(&mut self, data: DataType, addr: usize, dont_count: bool) -> Result<(), MemoryError>; 
     fn stats(&self) -> &MemStats;
 }
 
+/* one cache level: an access hits or misses, the caller fills on a miss */
+pub trait CacheAccess {
+    fn read(&mut self, addr: usize, size: DataTypeSize, dont_count: bool) -> Lookup<DataType>;
+    fn write(&mut self, data: DataType, addr: usize, dont_count: bool) -> Lookup<()>;
+    fn stats(&self) -> &MemStats;
+}
+
+/* line transfers between adjacent levels of the hierarchy */
 pub trait MemLevelAccess {
+    /* install a clean line (cache fill) */
     fn write_line(&mut self, addr: usize, words_per_lines: usize, data: Vec<u8>);
-    fn fetch_line(&self, addr: usize, words_per_lines: usize) -> Vec<u8>;
-
+    /* words_per_lines words starting at addr */
+    fn fetch_line(&mut self, addr: usize, words_per_lines: usize, dont_count: bool) -> Lookup<Vec<u8>>;
+    /* merge a dirty line evicted from the level above */
+    fn writeback_line(&mut self, addr: usize, words_per_lines: usize, data: Vec<u8>, dont_count: bool) -> Lookup<()>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DataTypeSize {
     Byte,
     Halfword,
@@ -77,74 +186,350 @@ impl DataTypeSize {
 }
 
 #[derive(Copy, Clone)]
-enum WhichL1 { Instr, Data }
+enum WhichL1 { Instr = 0, Data = 1 }
+
+impl WhichL1 {
+    fn name(self) -> &'static str {
+        match self {
+            WhichL1::Instr => "l1i",
+            WhichL1::Data  => "l1d",
+        }
+    }
+}
+
+/// How `Memory` picks the L1 a cached access goes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Routing {
+    /// fetches to the I-cache, loads and stores to the D-cache; dm_base
+    /// plays no part
+    Kind,
+    /// below dm_base the I-cache, above it the D-cache; for traces that do
+    /// not mark their fetches, like the r/w text traces
+    #[default]
+    Address,
+}
+
+impl FromStr for Routing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "kind"    => Ok(Routing::Kind),
+            "address" => Ok(Routing::Address),
+            _ => Err(format!("unknown routing '{s}' (expected kind or address)")),
+        }
+    }
+}
+
+/* ── runtime hierarchy description ───────────────────────────────────── */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryConfig {
+    #[serde(deserialize_with = "crate::config::de_size")]
+    pub main_bytes: usize,      // main memory size
+    #[serde(rename = "l1i")]
+    pub im: CacheConfig,        // instruction L1
+    #[serde(rename = "l1d")]
+    pub dm: CacheConfig,        // data L1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l2: Option<CacheConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l3: Option<CacheConfig>,
+    #[serde(deserialize_with = "crate::config::de_addr")]
+    pub dm_base: usize,         // start of data region
+    #[serde(deserialize_with = "crate::config::de_addr")]
+    pub mmio_base: usize,       // start of MMIO region
+    #[serde(default)]
+    pub seed: u64,              // drives every random eviction choice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_buffer: Option<WriteBufferConfig>,   // coalesces traffic into main
+    #[serde(default = "MemoryConfig::default_main_latency")]
+    pub main_latency: u64,      // cycles for main memory to return a line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<Routing>,   // unset: whatever the trace calls for, else Address
+}
+
+impl MemoryConfig {
+    pub const DEFAULT_MAIN_LATENCY: u64 = 100;
+
+    fn default_main_latency() -> u64 { Self::DEFAULT_MAIN_LATENCY }
+}
+
+/* ── default cache geometry ──────────────────────────────────────────── */
+const FULL_BYTES         : usize = 1 << 47;   // 128 TiB main memory, the user half of a 64-bit address space
+const IM_L1_BYTES        : usize = 1 << 14;   // 16 KiB I-cache
+const IM_L1_WORDS_PER_LN : usize = 8;         // 8 words / line
+const IM_L1_ASSOC        : usize = 2;         // 2-way LRU
+const DM_L1_BYTES        : usize = 1 << 13;   // 8 KiB D-cache
+const DM_L1_WORDS_PER_LN : usize = 4;         // 4 words / line, direct mapped
+
+const DM_BASE  : usize = 0x0060_0000;             // start of data region
+const MMIO_BASE: usize = 0xFFFF_8000_0000_0000;   // start of MMIO region, the kernel half
+
+/// The hierarchy the binary runs without `--config`: two L1s over main
+/// memory spanning a 64-bit process's address space (backed only where it
+/// is used), no L2/L3 and no write buffer.
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        MemoryConfig {
+            main_bytes: FULL_BYTES,
+            im: CacheConfig::set_associative(IM_L1_BYTES, IM_L1_WORDS_PER_LN, IM_L1_ASSOC, EvictionPolicy::Lru),
+            dm: CacheConfig::direct_mapped(DM_L1_BYTES, DM_L1_WORDS_PER_LN),
+            l2: None,
+            l3: None,
+            dm_base: DM_BASE,
+            mmio_base: MMIO_BASE,
+            seed: 0,
+            write_buffer: None,
+            main_latency: Self::DEFAULT_MAIN_LATENCY,
+            routing: None,
+        }
+    }
+}
 
 #[derive(Debug)]
-pub struct Memory<
-    const FULL_BYTES: usize,
-    const IM_L1_BYTES: usize,
-    const IM_L1_WORDS_PER_LINE: usize,
-    const DM_L1_BYTES: usize,
-    const DM_L1_WORDS_PER_LINE: usize,
-    const IM_L1_ASSOC: usize = 1,
-    const DM_L1_ASSOC: usize = 1,
-    > {
-        size: usize,                                     
+pub struct Memory {
         stats: MemStats,
-        im: Cache<IM_L1_BYTES, IM_L1_WORDS_PER_LINE, IM_L1_ASSOC>,
-        dm: Cache<DM_L1_BYTES, DM_L1_WORDS_PER_LINE, DM_L1_ASSOC>,
+        im: Cache,
+        dm: Cache,
+        lower: Vec<Cache>,                               // L2, L3, ... shared by I and D
         mmio_start_addr: usize,
         dm_start_addr: usize,
-        main: MainMemory<FULL_BYTES>,
-    }
-
-impl<
-    const FULL_BYTES: usize,
-    const IM_L1_BYTES: usize,
-    const IM_L1_WORDS_PER_LINE: usize,
-    const DM_L1_BYTES: usize,
-    const DM_L1_WORDS_PER_LINE: usize,
-    const IM_L1_ASSOC: usize,
-    const DM_L1_ASSOC: usize,
-    > Memory<
-    FULL_BYTES,
-    IM_L1_BYTES, IM_L1_WORDS_PER_LINE,
-    DM_L1_BYTES, DM_L1_WORDS_PER_LINE,
-    IM_L1_ASSOC, DM_L1_ASSOC >  {
-    pub fn new(mmio_start_addr: usize, dm_addr_start: usize) -> Self {
-        assert!(FULL_BYTES.is_power_of_two(), "main memory must be power of two");
-        assert!(IM_L1_BYTES.is_power_of_two(), "IM L1 size must be power of two");
-        assert!(IM_L1_WORDS_PER_LINE.is_power_of_two(), "IM line size must be power of two");
-        assert!(DM_L1_BYTES.is_power_of_two(), "DM L1 size must be power of two");
-        assert!(DM_L1_WORDS_PER_LINE.is_power_of_two(), "DM line size must be power of two");
-        // assert!(mmio_start_addr.is_power_of_two(), 
-            // "Instructions addr must start at pow of 2 {mmio_start_addr:x}");
-        // assert!(dm_addr_start.is_power_of_two(), 
-            // "Data addr must start at pow of 2 {dm_addr_start:x}");
-
-        Self {
-            mmio_start_addr: mmio_start_addr, 
-            dm_start_addr: dm_addr_start,
-            size: FULL_BYTES,
+        routing: Routing,
+        main: MainMemory,
+        write_buffer: Option<WriteBuffer>,               // in front of main
+        writes: Vec<(WriteHit, WriteMiss)>,              // l1i, l1d, then lower, in order
+        timing: Vec<(u64, u64)>,                         // (hit latency, miss penalty), same order
+        main_latency: u64,
+        issued: usize,                                   // accesses so far
+        now: Rc<Cell<usize>>,                            // index of the access in flight, for OPT
+        config: MemoryConfig,                            // what it was built from, for reports
+    }
+
+impl Memory {
+    pub fn new(config: &MemoryConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+
+        /* buffer entries are as wide as the lines of the level above main */
+        let last = config.l3.as_ref().or(config.l2.as_ref());
+        let line_bytes = last.map_or(config.im.line_bytes().max(config.dm.line_bytes()), |c| c.line_bytes());
+
+        Ok(Self {
+            mmio_start_addr: config.mmio_base, 
+            dm_start_addr: config.dm_base,
+            routing: config.routing.unwrap_or_default(),
             stats: MemStats::new(),
-            im: Cache::SetAssociative(SetAssocCache::<IM_L1_BYTES, IM_L1_WORDS_PER_LINE, IM_L1_ASSOC>::new(EvictionPolicy::Lru)),
-            // dm: Cache::SetAssociative(SetAssocCache::<DM_L1_BYTES, DM_L1_WORDS_PER_LINE, DM_L1_ASSOC>::new(EvictionPolicy::Lru)),
-            // im: Cache::DirectMapped(DMCache::<IM_L1_BYTES, IM_L1_WORDS_PER_LINE>::new()),
-            dm: Cache::DirectMapped(DMCache::<DM_L1_BYTES, DM_L1_WORDS_PER_LINE>::new()),
-            main: MainMemory::new(),
+            im: Cache::new(&config.im, level_seed(config.seed, 0)),
+            dm: Cache::new(&config.dm, level_seed(config.seed, 1)),
+            lower: [&config.l2, &config.l3].into_iter().flatten().zip(2..)
+                .map(|(cache, level)| Cache::new(cache, level_seed(config.seed, level)))
+                .collect(),
+            main: MainMemory::new(config.main_bytes),
+            write_buffer: config.write_buffer.as_ref().map(|wb| WriteBuffer::new(wb, line_bytes)),
+            writes: config.caches().map(|cache| (cache.write_hit, cache.write_miss)).collect(),
+            timing: config.caches().map(|cache| (cache.hit_latency, cache.miss_penalty)).collect(),
+            main_latency: config.main_latency,
+            issued: 0,
+            now: Rc::new(Cell::new(0)),
+            config: config.clone(),
+        })
+    }
+
+    /// Give every OPT level the addresses of the whole trace, in the order
+    /// `read`/`write` will be called with them.
+    pub fn set_future(&mut self, trace: &[usize]) {
+        for cache in [&mut self.im, &mut self.dm].into_iter().chain(self.lower.iter_mut()) {
+            if cache.uses_opt() {
+                let line_bytes = cache.words_per_line() * WORDSIZE;
+                cache.set_oracle(NextUse::new(trace, line_bytes, Rc::clone(&self.now)));
+            }
+        }
+    }
+
+    fn advance(&mut self) {
+        self.now.set(self.issued);
+        self.issued += 1;
+        if let Some(wb) = &mut self.write_buffer {
+            wb.tick(&mut self.main);
+        }
+    }
+
+    /// Retire whatever the write buffer still holds to main memory.
+    pub fn drain_write_buffer(&mut self) {
+        if let Some(wb) = &mut self.write_buffer {
+            wb.flush(&mut self.main);
+        }
+    }
+
+    /* None: an uncached MMIO access, whatever the routing; mmio_base
+     * decides what is cached, the routing only which L1 a cached access
+     * goes through */
+    #[inline(always)]
+    fn route(&self, kind: AccessKind, addr: usize) -> Option<WhichL1> {
+        if addr >= self.mmio_start_addr {
+            return None;
+        }
+        match (self.routing, kind) {
+            (Routing::Kind, AccessKind::IFetch) => Some(WhichL1::Instr),
+            (Routing::Kind, _)                  => Some(WhichL1::Data),
+            (Routing::Address, _)               => self.choose_cache(addr),
         }
     }
 
     #[inline(always)]
     fn choose_cache(&self, addr: usize) -> Option<WhichL1> {
-        if addr < self.dm_start_addr{
-            return Some(WhichL1::Instr);
-        } else if addr < self.mmio_start_addr {
-            return Some(WhichL1::Data);
+        if addr < self.dm_start_addr {
+            Some(WhichL1::Instr)
         } else {
-            return None;
+            Some(WhichL1::Data)
         }
-        
+    }
+
+    fn l1(&mut self, which: WhichL1) -> &mut Cache {
+        match which {
+            WhichL1::Instr => &mut self.im,
+            WhichL1::Data  => &mut self.dm,
+        }
+    }
+
+    /* ---------------- miss handling down the hierarchy ---------------- */
+    /* levels are numbered from the first shared cache (L2 = 0);
+     * level == lower.len() is main memory */
+
+    /* bring the line holding addr into an L1, writing back its victim first;
+     * Ok(cycles the fetch from below took), writebacks are not waited on */
+    fn fill_l1(&mut self, which: WhichL1, addr: usize) -> Result<u64, MemoryError> {
+        let l1 = self.l1(which);
+        let base = l1.get_base_addr(addr);
+        let wpl = l1.words_per_line();
+
+        if let Some((wb_addr, line)) = dirty_victim(l1, addr) {
+            self.write_back(0, wb_addr, wpl, line)?;
+        }
+        let (line, cycles) = self.fetch_from(0, base, wpl)?;
+        self.l1(which).write_line(base, wpl, line);
+        Ok(cycles)
+    }
+
+    /* same as fill_l1, for a shared level */
+    fn fill_lower(&mut self, level: usize, addr: usize) -> Result<u64, MemoryError> {
+        let cache = &self.lower[level];
+        let base = cache.get_base_addr(addr);
+        let wpl = cache.words_per_line();
+
+        if let Some((wb_addr, line)) = dirty_victim(cache, addr) {
+            self.write_back(level + 1, wb_addr, wpl, line)?;
+        }
+        let (line, cycles) = self.fetch_from(level + 1, base, wpl)?;
+        self.lower[level].write_line(base, wpl, line);
+        Ok(cycles)
+    }
+
+    /* wpl words at base, filling `level` first if it misses, and the
+     * cycles that took */
+    fn fetch_from(&mut self, level: usize, base: usize, wpl: usize) -> Result<(Vec<u8>, u64), MemoryError> {
+        if level == self.lower.len() {
+            let mut line = self.main.fetch_line(base, wpl, false).unwrap();
+            if let Some(wb) = &mut self.write_buffer {
+                wb.forward(base, &mut line);
+            }
+            self.main.record_cycles(self.main_latency);
+            return Ok((line, self.main_latency));
+        }
+
+        let (hit_latency, miss_penalty) = self.lower_timing(level);
+        let (line, cycles) = match self.lower[level].fetch_line(base, wpl, false) {
+            Lookup::Hit(line) => (line, hit_latency),
+            Lookup::Miss => {
+                let below = self.fill_lower(level, base)?;
+                (self.lower[level].fetch_line(base, wpl, true).unwrap(), hit_latency + miss_penalty + below)
+            }
+        };
+        self.lower[level].record_cycles(cycles);
+        Ok((line, cycles))
+    }
+
+    fn l1_writes(&self, which: WhichL1) -> (WriteHit, WriteMiss) {
+        self.writes[which as usize]
+    }
+
+    fn lower_writes(&self, level: usize) -> (WriteHit, WriteMiss) {
+        self.writes[2 + level]
+    }
+
+    fn l1_timing(&self, which: WhichL1) -> (u64, u64) {
+        self.timing[which as usize]
+    }
+
+    fn lower_timing(&self, level: usize) -> (u64, u64) {
+        self.timing[2 + level]
+    }
+
+    /* charge one access served through an L1 to it and to the whole hierarchy */
+    fn record_cycles(&mut self, which: WhichL1, cycles: u64) {
+        self.l1(which).record_cycles(cycles);
+        self.stats.record_cycles(cycles);
+    }
+
+    /* merge a dirty line into `level`; a miss allocates the line there
+     * first unless the level writes around */
+    fn write_back(&mut self, level: usize, addr: usize, wpl: usize, line: Vec<u8>) -> Result<(), MemoryError> {
+        if level == self.lower.len() {
+            if let Some(wb) = &mut self.write_buffer {
+                wb.write(addr, &line[..wpl * WORDSIZE], &mut self.main);
+                return Ok(());
+            }
+            self.main.writeback_line(addr, wpl, line, false);
+            return Ok(());
+        }
+
+        let (hit, miss) = self.lower_writes(level);
+        match self.lower[level].writeback_line(addr, wpl, line.clone(), false) {
+            Lookup::Hit(()) => {}
+            Lookup::Miss if miss == WriteMiss::Around => {
+                return self.write_back(level + 1, addr, wpl, line);
+            }
+            Lookup::Miss => {
+                self.fill_lower(level, addr)?;
+                self.lower[level].writeback_line(addr, wpl, line.clone(), true).unwrap();
+            }
+        }
+
+        if hit == WriteHit::Through {
+            self.lower[level].clean_line(addr);
+            self.write_back(level + 1, addr, wpl, line)?;
+        }
+        Ok(())
+    }
+
+    /* a single store passed down by a write-through or write-around level */
+    fn write_down(&mut self, level: usize, data: DataType, addr: usize) -> Result<(), MemoryError> {
+        if level == self.lower.len() {
+            if let Some(wb) = &mut self.write_buffer {
+                wb.write(addr, &data.to_le_bytes(), &mut self.main);
+                return Ok(());
+            }
+            return self.main.write(data, addr, false);
+        }
+
+        let (hit, miss) = self.lower_writes(level);
+        match self.lower[level].write(data, addr, false) {
+            Lookup::Hit(()) => {}
+            Lookup::Miss if miss == WriteMiss::Around => {
+                return self.write_down(level + 1, data, addr);
+            }
+            Lookup::Miss => {
+                self.fill_lower(level, addr)?;
+                self.lower[level].write(data, addr, true).unwrap();
+            }
+        }
+
+        if hit == WriteHit::Through {
+            self.lower[level].clean_line(addr);
+            self.write_down(level + 1, data, addr)?;
+        }
+        Ok(())
     }
 
     pub fn print_summary(&self) {
@@ -159,194 +544,159 @@ impl<
         let dm_l1_stats = self.dm.stats();
         dm_l1_stats.print_summary();
 
+        for (i, cache) in self.lower.iter().enumerate() {
+            println!("L{}", i + 2);
+            cache.stats().print_summary();
+        }
+
+        if let Some(wb) = &self.write_buffer {
+            println!("Write Buffer");
+            wb.print_summary();
+        }
+
         println!("Main");
         let mm_stats = self.main.stats();
         mm_stats.print_summary();
 
     }
-}
-
-impl<
-    const FULL_BYTES: usize,
-    const IM_L1_BYTES: usize,
-    const IM_L1_WORDS_PER_LINE: usize,
-    const DM_L1_BYTES: usize,
-    const DM_L1_WORDS_PER_LINE: usize,
-    const IM_L1_ASSOC: usize,
-    const DM_L1_ASSOC: usize,
-> MemoryAccess for Memory<
-    FULL_BYTES, 
-    IM_L1_BYTES, IM_L1_WORDS_PER_LINE, 
-    DM_L1_BYTES, DM_L1_WORDS_PER_LINE, 
-    DM_L1_ASSOC, IM_L1_ASSOC> {
-    fn read(&mut self, addr: usize, size: DataTypeSize, _: bool) -> Result<DataType, MemoryError> {
-        if addr >= self.mmio_start_addr {
-            return Ok(DataType::Word(0xcafebabe));
-            // return Err(MemoryError::OutOfBounds);
-        }
 
-        let align = DataTypeSize::get_size(size.clone());
-        if addr % align != 0 {
-            return Err(MemoryError::NotAligned);
-        }
-
-        match self.choose_cache(addr) {
-            Some(WhichL1::Instr) => {
-                match self.im.read(addr, size.clone(), false) {
-                    Ok(data) => {
-                        self.stats.record_hit();
-                        Ok(data)
-                    }
-
-                    Err(MemoryError::NotFound) => {
-                        self.stats.record_miss();
-
-
-                        let fetch_base_addr = self.im.get_base_addr(addr);
-
-                        if self.im.is_line_dirty(addr) {
-                            let write_back_addr = self.im.get_writeback_addr(addr);
-                            let write_back_line = self.im.get_evict_line_data(addr);
-                            self.main.write_line(write_back_addr, IM_L1_WORDS_PER_LINE, write_back_line);
-                        }
-
-                        // let new_line = self.main.fetch_line(fetch_base_addr, IM_L1_WORDS_PER_LINE);
-                        let new_line = if fetch_base_addr < self.size {
-                            self.main.fetch_line(fetch_base_addr, IM_L1_WORDS_PER_LINE)
-                        } else {
-                            vec![0; IM_L1_WORDS_PER_LINE * WORDSIZE]
-                        };
-                        
-                        self.im.write_line(fetch_base_addr, IM_L1_WORDS_PER_LINE, new_line);
-                        self.im.read(addr, size, true)
-                    }
-
-                    Err(e) => Err(e),
-                }
-            }
-            Some(WhichL1::Data) => {
-                match self.dm.read(addr, size.clone(), false) {
-                    Ok(data) => {
-                        self.stats.record_hit();
-                        Ok(data)
-                    }
+    pub fn config(&self) -> &MemoryConfig {
+        &self.config
+    }
 
-                    Err(MemoryError::NotFound) => {
-                        self.stats.record_miss();
+    /// Every level's geometry and counters, ready for `write_json`/`write_csv`.
+    pub fn report(&self) -> Report<'_> {
+        let mut levels = vec![
+            LevelReport::new("memory", None, &self.stats),
+            LevelReport::new("l1i", Some(&self.config.im), self.im.stats()),
+            LevelReport::new("l1d", Some(&self.config.dm), self.dm.stats()),
+        ];
+        let lower_configs = [&self.config.l2, &self.config.l3].into_iter().flatten();
+        for (i, (cache, config)) in self.lower.iter().zip(lower_configs).enumerate() {
+            levels.push(LevelReport::new(format!("l{}", i + 2), Some(config), cache.stats()));
+        }
+        levels.push(LevelReport::new("main", None, self.main.stats()));
+
+        Report {
+            config: &self.config,
+            levels,
+            write_buffer: self.write_buffer.as_ref().map(|wb| WriteBufferReport {
+                held: wb.occupancy(),
+                avg_occupancy: wb.stats().avg_occupancy(),
+                stats: wb.stats(),
+            }),
+        }
+    }
+}
 
+/* independent, reproducible stream per level from the one user seed */
+fn level_seed(seed: u64, level: u64) -> u64 {
+    seed ^ level.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
 
-                        let fetch_base_addr = self.dm.get_base_addr(addr);
+/* address and contents of the dirty line a fill for addr would evict */
+fn dirty_victim(cache: &Cache, addr: usize) -> Option<(usize, Vec<u8>)> {
+    cache.is_line_dirty(addr)
+        .then(|| (cache.get_writeback_addr(addr), cache.get_evict_line_data(addr)))
+}
 
-                        if self.dm.is_line_dirty(addr) {
-                            let write_back_addr = self.dm.get_writeback_addr(addr);
-                            let write_back_line = self.dm.get_evict_line_data(addr);
-                            self.main.write_line(write_back_addr, DM_L1_WORDS_PER_LINE, write_back_line);
-                        }
+impl MemoryAccess for Memory {
+    fn read(&mut self, addr: usize, size: DataTypeSize, _: bool) -> Result<DataType, MemoryError> {
+        self.load(AccessKind::Load, addr, size)
+    }
 
+    fn fetch(&mut self, addr: usize, size: DataTypeSize, _: bool) -> Result<DataType, MemoryError> {
+        self.load(AccessKind::IFetch, addr, size)
+    }
 
-                        let new_line = if fetch_base_addr < self.size {
-                            self.main.fetch_line(fetch_base_addr, DM_L1_WORDS_PER_LINE)
-                        } else {
-                            vec![0; DM_L1_WORDS_PER_LINE * WORDSIZE]
-                        };
+    fn write(&mut self, data: DataType, addr: usize, _: bool) -> Result<(), MemoryError> {
+        self.advance();
+        /* no device model sits behind MMIO: the store is dropped */
+        let Some(which) = self.route(AccessKind::Store, addr) else {
+            return Ok(());
+        };
+        self.write_l1(which, data, addr)
+    }
 
-                        // let new_line = self.main.fetch_line(fetch_base_addr, DM_L1_WORDS_PER_LINE);
-                        self.dm.write_line(fetch_base_addr, DM_L1_WORDS_PER_LINE, new_line);
-                        self.dm.read(addr, size, true)
-                    }
+    fn stats(&self) -> &MemStats {
+        &self.stats
+    }
+}
 
-                    Err(e) => Err(e),
-                }
-            }
-            _ => Err(MemoryError::NotCompatible),
-        }
+impl Memory {
+    /* ---------------- one access through an L1 ---------------- */
+    fn load(&mut self, kind: AccessKind, addr: usize, size: DataTypeSize) -> Result<DataType, MemoryError> {
+        self.advance();
+        /* no device model sits behind MMIO: every register reads as zero */
+        let Some(which) = self.route(kind, addr) else {
+            return Ok(DataType::zero(size));
+        };
+        self.read_l1(which, addr, size)
     }
 
-    fn write(&mut self, data: DataType, addr: usize, _: bool) -> Result<(), MemoryError> {
-        if addr >= self.mmio_start_addr {
-            return Ok(());
-            // return Err(MemoryError::OutOfBounds);
+    fn read_l1(&mut self, which: WhichL1, addr: usize, size: DataTypeSize) -> Result<DataType, MemoryError> {
+        let align = DataTypeSize::get_size(size.clone());
+        if !addr.is_multiple_of(align) {
+            return Err(MemoryError::new(MemoryErrorKind::NotAligned, addr, align, which.name()));
         }
+        /* a cached access must be backed, or its line would be made up */
+        self.main.check(addr, align)?;
 
-        let align = data.payload_size();
-        if addr % align != 0 {
-            return Err(MemoryError::NotAligned);
-        }
-
-        match self.choose_cache(addr) {
-            Some(WhichL1::Instr) => {
-                match self.im.write(data, addr, false) {
-                    Ok(()) => {
-                        self.stats.record_hit();
-                        Ok(())
-                    }
-
-                    Err(MemoryError::NotFound) => {
-                        self.stats.record_miss();
-
-                        let fetch_base_addr = self.im.get_base_addr(addr);
-
-                        if self.im.is_line_dirty(addr) {
-                            let write_back_addr = self.im.get_writeback_addr(addr);
-                            let write_back_line = self.im.get_evict_line_data(addr);
-                            self.main.write_line(write_back_addr, IM_L1_WORDS_PER_LINE, write_back_line);
-                        }
-
-                        let new_line = if fetch_base_addr < self.size {
-                            self.main.fetch_line(fetch_base_addr, IM_L1_WORDS_PER_LINE)
-                        } else {
-                            vec![0; IM_L1_WORDS_PER_LINE * WORDSIZE]
-                        };
-                        // let new_line = self.main.fetch_line(fetch_base_addr, IM_L1_WORDS_PER_LINE);
-                        self.im.write_line(fetch_base_addr, IM_L1_WORDS_PER_LINE, new_line);
-                        self.im.write(data, addr, true)
-                    }
-
-                    Err(e) => Err(e),
-                }
+        let (hit_latency, miss_penalty) = self.l1_timing(which);
 
+        let (data, cycles) = match self.l1(which).read(addr, size.clone(), false) {
+            Lookup::Hit(data) => {
+                self.stats.record_hit(Access::Read);
+                (data, hit_latency)
             }
-            Some(WhichL1::Data) => {
-                match self.dm.write(data, addr, false) {
-                    Ok(()) => {
-                        self.stats.record_hit();
-                        Ok(())
-                    }
-
-                    Err(MemoryError::NotFound) => {
-                        self.stats.record_miss();
-
-                        let fetch_base_addr = self.dm.get_base_addr(addr);
-
-                        if self.dm.is_line_dirty(addr) {
-                            let write_back_addr = self.dm.get_writeback_addr(addr);
-                            let write_back_line = self.dm.get_evict_line_data(addr);
-                            self.main.write_line(write_back_addr, DM_L1_WORDS_PER_LINE, write_back_line);
-                        }
-
-                        // let new_line = self.main.fetch_line(fetch_base_addr, DM_L1_WORDS_PER_LINE);
-                        let new_line = if fetch_base_addr < self.size {
-                            self.main.fetch_line(fetch_base_addr, DM_L1_WORDS_PER_LINE)
-                        } else {
-                            vec![0; DM_L1_WORDS_PER_LINE * WORDSIZE]
-                        };
-                        self.dm.write_line(fetch_base_addr, DM_L1_WORDS_PER_LINE, new_line);
-                        self.dm.write(data, addr, true)
-                    }
-
-                    Err(e) => Err(e),
-                }
+
+            Lookup::Miss => {
+                self.stats.record_miss(Access::Read);
+                let below = self.fill_l1(which, addr)?;
+                (self.l1(which).read(addr, size, true).unwrap(), hit_latency + miss_penalty + below)
             }
-            _ => Err(MemoryError::NotCompatible),
-        }
+        };
+        self.record_cycles(which, cycles);
+        self.stats.record_read(data.payload_size());
+        Ok(data)
+    }
 
+    fn write_l1(&mut self, which: WhichL1, data: DataType, addr: usize) -> Result<(), MemoryError> {
+        let align = data.payload_size();
+        if !addr.is_multiple_of(align) {
+            return Err(MemoryError::new(MemoryErrorKind::NotAligned, addr, align, which.name()));
+        }
+        self.main.check(addr, align)?;
 
-    }
+        let (hit, miss) = self.l1_writes(which);
+        let (hit_latency, miss_penalty) = self.l1_timing(which);
+        self.stats.record_write(data.payload_size());
 
+        let cycles = match self.l1(which).write(data, addr, false) {
+            Lookup::Hit(()) => {
+                self.stats.record_hit(Access::Write);
+                hit_latency
+            }
 
+            Lookup::Miss => {
+                self.stats.record_miss(Access::Write);
+                if miss == WriteMiss::Around {
+                    /* posted: the store does not wait on the levels below */
+                    self.record_cycles(which, hit_latency + miss_penalty);
+                    return self.write_down(0, data, addr);
+                }
+                let below = self.fill_l1(which, addr)?;
+                self.l1(which).write(data, addr, true).unwrap();
+                hit_latency + miss_penalty + below
+            }
+        };
+        self.record_cycles(which, cycles);
 
-    fn stats(&self) -> &MemStats {
-        &self.stats
+        if hit == WriteHit::Through {
+            self.l1(which).clean_line(addr);
+            self.write_down(0, data, addr)?;
+        }
+        Ok(())
     }
 }
 
@@ -356,13 +706,24 @@ mod tests {
 
     const EPSILON: f64 = 0.001;
 
+    /* direct-mapped L1s, MMIO region pushed out of the way */
+    fn mem(main_bytes: usize, im_bytes: usize, im_wpl: usize, dm_bytes: usize, dm_wpl: usize, dm_base: usize) -> Memory {
+        Memory::new(&MemoryConfig {
+            main_bytes,
+            im: CacheConfig::direct_mapped(im_bytes, im_wpl),
+            dm: CacheConfig::direct_mapped(dm_bytes, dm_wpl),
+            dm_base,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        }).expect("valid test hierarchy")
+    }
+
     #[test]
     fn new_empty_cache_has_no_data() {
         const MEM_SIZE: usize = 1 << 6;
         const L1_SIZE: usize = 1 << 4;
         const W_P_L: usize = 2;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
-        let mem = Mem::new(0, 2 * L1_SIZE);
+        let mem = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, 2 * L1_SIZE);
         println!("{:#?}", mem);
     }
 
@@ -371,21 +732,18 @@ mod tests {
         const MEM_SIZE: usize = 1 << 12;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 4;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
-        let mut m = Mem::new(0, L1_SIZE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, L1_SIZE);
 
         let addr = 0x10;
         let byte = DataType::Byte(0xff);
 
-        let _ = m.write(byte, addr); // cache miss, but still writes
+        let _ = m.write(byte, addr, false); // cache miss, but still writes
     
         // FIXME: there has to be a cleaner method
-        let mut dut_byte: DataType;
-        match m.read(addr, DataTypeSize::Byte) {
-            Ok(b) => dut_byte = b,  
-            Err(MemoryError::NotFound) => panic!("mem error"),
-            _ => panic!("idk")
-        }
+        let dut_byte: DataType = match m.read(addr, DataTypeSize::Byte, false) {
+            Ok(b) => b,
+            Err(e) => panic!("{e}"),
+        };
         assert_eq!(dut_byte, byte);
 
         assert_eq!(m.stats.total_accesses(), 2);
@@ -398,24 +756,23 @@ mod tests {
         const MEM_SIZE: usize = 1 << 12;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 4;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
-        let mut m = Mem::new(IM_BASE, IM_BASE + L1_SIZE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, IM_BASE + L1_SIZE);
 
         // write line to main mem and check values
         let expected_data: Vec<u32> = (0..W_P_L).map(|i| i as u32).collect();
 
         // write data to main mem
-        for i in 0..W_P_L {
+        for (i, &w) in expected_data.iter().enumerate() {
             let addr = i * WORDSIZE + IM_BASE;
-            let _ = m.main.write(DataType::Word(expected_data[i]), addr);
+            let _ = m.main.write(DataType::Word(w), addr, false);
         }
 
         // cause a miss and fetch line
-        for i in 0..W_P_L {
+        for (i, &expected) in expected_data.iter().enumerate() {
             let addr = i * WORDSIZE + IM_BASE;
-            match m.read(addr, DataTypeSize::Word) {
-                Ok(DataType::Word(w)) => assert_eq!(w, expected_data[i]),
+            match m.read(addr, DataTypeSize::Word, false) {
+                Ok(DataType::Word(w)) => assert_eq!(w, expected),
                 _ => panic!("Incorrect read @ {:#?}",addr)
             }
         }
@@ -436,13 +793,12 @@ mod tests {
         const MEM_SIZE: usize = 1 << 12;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 4;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
-        let mut m = Mem::new(IM_BASE, IM_BASE + L1_SIZE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, IM_BASE + L1_SIZE);
 
         for i in 0..W_P_L+1 {
             let addr = i * WORDSIZE + IM_BASE;
-            let _ = m.read(addr, DataTypeSize::Word);
+            let _ = m.read(addr, DataTypeSize::Word, false);
         }
 
         m.print_summary();
@@ -462,9 +818,8 @@ mod tests {
         const MEM_SIZE: usize = 1 << 12;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 4;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
-        let mut m = Mem::new(IM_BASE, IM_BASE + 3 * L1_SIZE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, IM_BASE + 3 * L1_SIZE);
 
         // cause a miss and write to the cache
         let bb = m.im.byte_bits(); // lowest bits
@@ -474,17 +829,17 @@ mod tests {
         let addr1 = (1 << (ib + wb + bb)) | (0x8 << ib) | 
                             (0x2 << wb) | (0x0 << bb);
         let data1 = DataType::Word(0xcafebabe);
-        let _ = m.write(data1.clone(), addr1.clone());
+        let _ = m.write(data1, addr1, false);
 
         // get mapped to the same index and evict the old line
         let addr2 = (2 << (ib + wb + bb)) | (0x8 << ib) | 
                             (0x2 << wb) | (0x0 << bb);
-        match m.read(addr2, DataTypeSize::Word) {
+        match m.read(addr2, DataTypeSize::Word, false) {
             Ok(w) => assert_ne!(w, data1),
             _=> panic!("[MEMORY] errror here")
         }
 
-        match m.read(addr1, DataTypeSize::Word) {
+        match m.read(addr1, DataTypeSize::Word, false) {
             Ok(w) => assert_eq!(w, data1, "[MEMORY] write-back or reload failed"),
             Err(e) => panic!("[MEMORY] read error: {e:?}"),
         }
@@ -501,9 +856,8 @@ mod tests {
         const MEM_SIZE: usize = 1 << 12;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 8;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
-        let mut m = Mem::new(IM_BASE, IM_BASE + 3 * L1_SIZE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, IM_BASE + 3 * L1_SIZE);
 
         // cause a miss and write to the cache
         let bb = m.im.byte_bits(); // lowest bits
@@ -513,17 +867,17 @@ mod tests {
         let addr1 = (1 << (ib + wb + bb)) | (0x8 << ib) | 
                             (0x4 << wb) | (0x0 << bb);
         let data1 = DataType::Word(0xcafebabe);
-        let _ = m.write(data1.clone(), addr1.clone());
+        let _ = m.write(data1, addr1, false);
 
         // get mapped to the same index and evict the old line
         let addr2 = (2 << (ib + wb + bb)) | (0x8 << ib) | 
                             (0x4 << wb) | (0x0 << bb);
-        match m.read(addr2, DataTypeSize::Word) {
+        match m.read(addr2, DataTypeSize::Word, false) {
             Ok(w) => assert_ne!(w, data1),
             _=> panic!("[MEMORY] errror here")
         }
 
-        match m.read(addr1, DataTypeSize::Word) {
+        match m.read(addr1, DataTypeSize::Word, false) {
             Ok(w) => assert_eq!(w, data1, "[MEMORY] write-back or reload failed"),
             Err(e) => panic!("[MEMORY] read error: {e:?}"),
         }
@@ -540,20 +894,19 @@ mod tests {
         const MEM_SIZE: usize = 1 << 12;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 4;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
         const DM_BASE: usize = IM_BASE + 2 * L1_SIZE;
-        let mut m = Mem::new(IM_BASE, DM_BASE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, DM_BASE);
 
         for i in IM_BASE..DM_BASE/WORDSIZE {
             let addr = i * WORDSIZE;
-            let _ = m.read(addr, DataTypeSize::Word);
+            let _ = m.read(addr, DataTypeSize::Word, false);
         }
 
         m.print_summary();
 
         let expected_hit = (W_P_L-1) as f64 / W_P_L as f64;
-        let expected_miss = 1 as f64 / W_P_L as f64;
+        let expected_miss = 1.0 / W_P_L as f64;
 
         assert_eq!(m.stats.total_accesses(), DM_BASE/WORDSIZE, "Incorrect accesses");
         assert!((m.stats.hit_rate() - expected_hit).abs() < EPSILON, "Incorrect Hit Rate");
@@ -566,29 +919,26 @@ mod tests {
         const MEM_SIZE: usize = 1 << 12;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 4;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
         const DM_BASE: usize = IM_BASE + 2 * L1_SIZE;
-        let mut m = Mem::new(IM_BASE, DM_BASE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, DM_BASE);
 
         let addr = 0x10 + DM_BASE;
         let byte = DataType::Byte(0xff);
 
-        let _ = m.write(byte, addr); // cache miss, but still writes
+        let _ = m.write(byte, addr, false); // cache miss, but still writes
     
         // FIXME: there has to be a cleaner method
-        let mut dut_byte: DataType;
-        match m.read(addr, DataTypeSize::Byte) {
-            Ok(b) => dut_byte = b,  
-            Err(MemoryError::NotFound) => panic!("mem error"),
-            _ => panic!("idk")
-        }
+        let dut_byte: DataType = match m.read(addr, DataTypeSize::Byte, false) {
+            Ok(b) => b,
+            Err(e) => panic!("{e}"),
+        };
         assert_eq!(dut_byte, byte);
 
         m.print_summary();
 
         assert_eq!(m.stats.total_accesses(), 2);
-        assert_eq!(m.dm.stats().total_accesses(), 3);
+        assert_eq!(m.dm.stats().total_accesses(), 2); // refill after the miss is not counted
         assert_eq!(m.stats.hit_rate(), 0.5);
         assert_eq!(m.stats.miss_rate(), 0.5);
     }
@@ -598,25 +948,24 @@ mod tests {
         const MEM_SIZE: usize = 1 << 16;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 8;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
         const DM_BASE: usize = IM_BASE + 2 * L1_SIZE;
-        let mut m = Mem::new(IM_BASE, DM_BASE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, DM_BASE);
 
         // write line to main mem and check values
         let expected_data: Vec<u32> = (0..W_P_L).map(|i| i as u32).collect();
 
         // write data to main mem
-        for i in 0..W_P_L {
+        for (i, &w) in expected_data.iter().enumerate() {
             let addr = i * WORDSIZE + DM_BASE;
-            let _ = m.main.write(DataType::Word(expected_data[i]), addr);
+            let _ = m.main.write(DataType::Word(w), addr, false);
         }
 
         // cause a miss and fetch line
-        for i in 0..W_P_L {
+        for (i, &expected) in expected_data.iter().enumerate() {
             let addr = i * WORDSIZE + DM_BASE;
-            match m.read(addr, DataTypeSize::Word) {
-                Ok(DataType::Word(w)) => assert_eq!(w, expected_data[i]),
+            match m.read(addr, DataTypeSize::Word, false) {
+                Ok(DataType::Word(w)) => assert_eq!(w, expected),
                 _ => panic!("Incorrect read @ {:#?}",addr)
             }
         }
@@ -637,14 +986,13 @@ mod tests {
         const MEM_SIZE: usize = 1 << 16;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 8;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
         const DM_BASE: usize = IM_BASE + 2 * L1_SIZE;
-        let mut m = Mem::new(IM_BASE, DM_BASE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, DM_BASE);
 
         for i in 0..W_P_L+1 {
             let addr = i * WORDSIZE + DM_BASE;
-            let _ = m.read(addr, DataTypeSize::Word);
+            let _ = m.read(addr, DataTypeSize::Word, false);
         }
 
         m.print_summary();
@@ -664,10 +1012,9 @@ mod tests {
         const MEM_SIZE: usize = 1 << 16;
         const L1_SIZE: usize = 1 << 10;
         const W_P_L: usize = 8;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
         const DM_BASE: usize = IM_BASE + 2 * L1_SIZE;
-        let mut m = Mem::new(IM_BASE, DM_BASE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, DM_BASE);
 
         // cause a miss and write to the cache
         let bb = m.dm.byte_bits(); // lowest bits
@@ -677,17 +1024,17 @@ mod tests {
         let addr1 = ((1 << (ib + wb + bb)) | (0x8 << ib) | 
                             (0x4 << wb) | (0x0 << bb)) + DM_BASE ;
         let data1 = DataType::Word(0xcafebabe);
-        let _ = m.write(data1.clone(), addr1.clone());
+        let _ = m.write(data1, addr1, false);
 
         // get mapped to the same index and evict the old line
         let addr2 = ((2 << (ib + wb + bb)) | (0x8 << ib) | 
                             (0x4 << wb) | (0x0 << bb)) + DM_BASE;
-        match m.read(addr2, DataTypeSize::Word) {
+        match m.read(addr2, DataTypeSize::Word, false) {
             Ok(w) => assert_ne!(w, data1),
             _=> panic!("[MEMORY] errror here")
         }
 
-        match m.read(addr1, DataTypeSize::Word) {
+        match m.read(addr1, DataTypeSize::Word, false) {
             Ok(w) => assert_eq!(w, data1, "[MEMORY] write-back or reload failed"),
             Err(e) => panic!("[MEMORY] read error: {e:?}"),
         }
@@ -704,21 +1051,20 @@ mod tests {
         const MEM_SIZE: usize = 1 << 16;
         const L1_SIZE: usize = 1 << 12;
         const W_P_L: usize = 8;
-        type Mem = Memory<MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L>;
         const IM_BASE: usize = 0;
         const DM_BASE: usize = IM_BASE + 2 * L1_SIZE;
-        let mut m = Mem::new(IM_BASE, DM_BASE);
+        let mut m = mem(MEM_SIZE, L1_SIZE, W_P_L, L1_SIZE, W_P_L, DM_BASE);
 
         for i in DM_BASE..MEM_SIZE {
             if i % WORDSIZE != 0 { continue; }
             let addr = i;
-            let _ = m.read(addr, DataTypeSize::Word);
+            let _ = m.read(addr, DataTypeSize::Word, false);
         }
 
         m.print_summary();
 
         let expected_hit = (W_P_L-1) as f64 / W_P_L as f64;
-        let expected_miss = 1 as f64 / W_P_L as f64;
+        let expected_miss = 1.0 / W_P_L as f64;
 
         assert_eq!(m.stats.total_accesses(), (MEM_SIZE - DM_BASE)/WORDSIZE, "Incorrect accesses");
         assert!((m.stats.hit_rate() - expected_hit).abs() < EPSILON, "Incorrect Hit Rate");
@@ -726,6 +1072,40 @@ mod tests {
         assert_eq!(m.im.stats().total_accesses(), 0, "Incorrect accesses");
     }
 
+    #[test]
+    fn unset_routing_splits_by_address() {
+        /* what every r/w trace and config written before routing existed gets */
+        let mut m = Memory::new(&MemoryConfig { dm_base: 0x1000, ..MemoryConfig::default() }).unwrap();
+        m.read(0x0800, DataTypeSize::Word, false).unwrap();
+        m.read(0x2000, DataTypeSize::Word, false).unwrap();
+
+        assert_eq!(m.im.stats().total_accesses(), 1);
+        assert_eq!(m.dm.stats().total_accesses(), 1);
+    }
+
+    #[test]
+    fn kind_routing_ignores_dm_base_but_not_mmio_base() {
+        let mut m = Memory::new(&MemoryConfig {
+            main_bytes: 1 << 16,
+            im: CacheConfig::direct_mapped(1 << 10, 4),
+            dm: CacheConfig::direct_mapped(1 << 10, 4),
+            dm_base: 1 << 12,
+            mmio_base: 1 << 14,
+            routing: Some(Routing::Kind),
+            ..MemoryConfig::default()
+        }).unwrap();
+
+        m.read(0x100, DataTypeSize::Word, false).unwrap();          // a constant pool in code
+        m.write(DataType::Word(5), 0x3000, false).unwrap();         // data above dm_base
+        m.fetch(0x2000, DataTypeSize::Word, false).unwrap();        // code above dm_base
+        m.write(DataType::Word(5), 0x8000, false).unwrap();         // a device register
+        assert_eq!(m.read(0x8000, DataTypeSize::Byte, false), Ok(DataType::Byte(0)));   // and reads as zero
+
+        assert_eq!(m.dm.stats().total_accesses(), 2);
+        assert_eq!(m.im.stats().total_accesses(), 1);
+        assert_eq!(m.main.stats().total_accesses(), 3, "only the three line fills");
+    }
+
     #[test]
     fn diff_size_l1s() {
         const MEM_SIZE: usize = 1 << 16;
@@ -733,18 +1113,412 @@ mod tests {
         const IM_W_P_L: usize = 16;
         const DM_L1_SIZE: usize = 1 << 13;
         const DM_W_P_L: usize = 8;
-        type Mem = Memory<MEM_SIZE, IM_L1_SIZE, IM_W_P_L, DM_L1_SIZE, DM_W_P_L>;
-        const IM_BASE: usize = 0;
         const DM_BASE: usize = 0x6000;
-        let mut m = Mem::new(IM_BASE, DM_BASE);
+        let mut m = mem(MEM_SIZE, IM_L1_SIZE, IM_W_P_L, DM_L1_SIZE, DM_W_P_L, DM_BASE);
 
-        for i in (0..MEM_SIZE).into_iter().step_by(WORDSIZE) {
-            let _ = m.read(i, DataTypeSize::Word);
+        for i in (0..MEM_SIZE).step_by(WORDSIZE) {
+            let _ = m.read(i, DataTypeSize::Word, false);
         }
 
         m.print_summary();
     }
 
+    #[test]
+    fn dm_fully_associative() {
+        const MEM_SIZE: usize = 1 << 17;
+        const L1_SIZE: usize = 1 << 10;
+        const W_P_L: usize = 4;
+        const DM_BASE: usize = 2 * L1_SIZE;
+        let mut m = Memory::new(&MemoryConfig {
+            main_bytes: MEM_SIZE,
+            im: CacheConfig::direct_mapped(L1_SIZE, W_P_L),
+            dm: CacheConfig::fully_associative(L1_SIZE, W_P_L, EvictionPolicy::Lru),
+            dm_base: DM_BASE,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        }).unwrap();
+
+        // lines one L1 apart all collide in a direct-mapped cache
+        let lines = L1_SIZE / (W_P_L * WORDSIZE);
+        let addrs: Vec<usize> = (0..lines).map(|i| DM_BASE + i * L1_SIZE).collect();
+        for (i, &addr) in addrs.iter().enumerate() {
+            let _ = m.write(DataType::Word(i as u32), addr, false);
+        }
+        for (i, &addr) in addrs.iter().enumerate() {
+            match m.read(addr, DataTypeSize::Word, false) {
+                Ok(DataType::Word(w)) => assert_eq!(w, i as u32),
+                other => panic!("Incorrect read @ {addr:#x}: {other:?}"),
+            }
+        }
+
+        m.print_summary();
+
+        assert_eq!(m.stats.total_accesses(), 2 * lines);
+        assert_eq!(m.stats.hit_rate(), 0.5, "only the first touch of each line may miss");
+    }
+
+    /* direct-mapped L1s over a unified L2 */
+    fn mem_l2(l1_bytes: usize, l2: CacheConfig, dm_base: usize) -> Memory {
+        Memory::new(&MemoryConfig {
+            main_bytes: 1 << 16,
+            im: CacheConfig::direct_mapped(l1_bytes, 4),
+            dm: CacheConfig::direct_mapped(l1_bytes, 4),
+            l2: Some(l2),
+            dm_base,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        }).expect("valid test hierarchy")
+    }
+
+    #[test]
+    fn l2_hit_after_l1_eviction() {
+        const L1_SIZE: usize = 1 << 8;
+        const DM_BASE: usize = 1 << 12;
+        let mut m = mem_l2(L1_SIZE, CacheConfig::set_associative(1 << 12, 4, 4, EvictionPolicy::Lru), DM_BASE);
+
+        // two lines one L1 apart collide in the direct-mapped L1 but not in L2
+        let (a, b) = (DM_BASE, DM_BASE + L1_SIZE);
+        for addr in [a, b, a] {
+            let _ = m.read(addr, DataTypeSize::Word, false);
+        }
+
+        assert_eq!(m.dm.stats().misses(), 3, "every access conflicts in L1");
+        assert_eq!(m.lower[0].stats().misses(), 2, "L2 misses only on first touch");
+        assert_eq!(m.lower[0].stats().hits(), 1, "the re-read of a is served by L2");
+        assert_eq!(m.im.stats().total_accesses(), 0);
+    }
+
+    #[test]
+    fn rrip_resists_a_scan_through_the_hierarchy() {
+        const ASSOC: usize = 4;
+        const LINE: usize = 4 * WORDSIZE;
+        let hot_line_survives = |policy| {
+            let mut m = Memory::new(&MemoryConfig {
+                main_bytes: 1 << 16,
+                im: CacheConfig::direct_mapped(1 << 8, 4),
+                dm: CacheConfig::set_associative(ASSOC * LINE, 4, ASSOC, policy),   // a single set
+                dm_base: 0,
+                mmio_base: usize::MAX,
+                ..MemoryConfig::default()
+            }).unwrap();
+
+            /* one hot line, then a stream of lines read once each */
+            m.read(0, DataTypeSize::Word, false).unwrap();
+            m.read(0, DataTypeSize::Word, false).unwrap();
+            for line in 1..=2 * ASSOC - 2 {
+                m.read(line * LINE, DataTypeSize::Word, false).unwrap();
+            }
+            let hits = m.dm.stats().hits();
+            m.read(0, DataTypeSize::Word, false).unwrap();
+            m.dm.stats().hits() > hits
+        };
+
+        for policy in [EvictionPolicy::Srrip, EvictionPolicy::Brrip, EvictionPolicy::Drrip] {
+            assert!(hot_line_survives(policy), "{policy:?} lost the hot line to the scan");
+        }
+        assert!(!hot_line_survives(EvictionPolicy::Lru), "LRU keeps only the last {ASSOC} lines");
+    }
+
+    #[test]
+    fn fully_associative_nru_through_the_hierarchy() {
+        const LINE: usize = 4 * WORDSIZE;
+        let first_line_survives = |policy| {
+            let mut m = Memory::new(&MemoryConfig {
+                main_bytes: 1 << 16,
+                im: CacheConfig::direct_mapped(1 << 8, 4),
+                dm: CacheConfig::fully_associative(4 * LINE, 4, policy),
+                dm_base: 0,
+                mmio_base: usize::MAX,
+                ..MemoryConfig::default()
+            }).unwrap();
+
+            /* line 0 is the least recent but the only one referenced since
+             * its fill; line 4 replaces the lowest unreferenced line, 1 */
+            for line in [0, 0, 1, 2, 3, 4] {
+                m.read(line * LINE, DataTypeSize::Word, false).unwrap();
+            }
+            let hits = m.dm.stats().hits();
+            m.read(0, DataTypeSize::Word, false).unwrap();
+            m.dm.stats().hits() > hits
+        };
+
+        assert!(first_line_survives(EvictionPolicy::Nru), "NRU evicted a referenced line");
+        assert!(!first_line_survives(EvictionPolicy::Lru), "LRU evicts the least recent line");
+    }
+
+    #[test]
+    fn dirty_writeback_lands_in_l2() {
+        const L1_SIZE: usize = 1 << 8;
+        const DM_BASE: usize = 1 << 12;
+        let mut m = mem_l2(L1_SIZE, CacheConfig::set_associative(1 << 12, 8, 2, EvictionPolicy::Lru), DM_BASE);
+
+        let (a, b) = (DM_BASE + 0x24, DM_BASE + L1_SIZE + 0x24);
+        m.write(DataType::Word(0xcafebabe), a, false).unwrap();
+        m.read(b, DataTypeSize::Word, false).unwrap();   // evicts the dirty line holding a
+
+        // the data lives in L2 only; main memory has not seen it yet
+        match m.main.read(a, DataTypeSize::Word, false) {
+            Ok(DataType::Word(w)) => assert_eq!(w, 0),
+            other => panic!("main read failed: {other:?}"),
+        }
+        match m.read(a, DataTypeSize::Word, false) {
+            Ok(w) => assert_eq!(w, DataType::Word(0xcafebabe), "write-back to L2 lost"),
+            Err(e) => panic!("[MEMORY] read error: {e:?}"),
+        }
+
+        // fill a, fill b, write back a (hit: a's L2 line was filled first), refill a
+        assert_eq!(m.lower[0].stats().misses(), 2);
+        assert_eq!(m.lower[0].stats().hits(), 2);
+    }
+
+    #[test]
+    fn three_levels_reach_main() {
+        const L1_SIZE: usize = 1 << 8;
+        const DM_BASE: usize = 1 << 12;
+        let mut m = Memory::new(&MemoryConfig {
+            main_bytes: 1 << 16,
+            im: CacheConfig::direct_mapped(L1_SIZE, 4),
+            dm: CacheConfig::direct_mapped(L1_SIZE, 4),
+            l2: Some(CacheConfig::direct_mapped(1 << 10, 8)),
+            l3: Some(CacheConfig::fully_associative(1 << 12, 16, EvictionPolicy::Lru)),
+            dm_base: DM_BASE,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        }).unwrap();
+
+        // sweep a region larger than L2 twice, writing then reading back
+        let region = 1 << 11;
+        for addr in (DM_BASE..DM_BASE + region).step_by(WORDSIZE) {
+            m.write(DataType::Word(addr as u32), addr, false).unwrap();
+        }
+        for addr in (DM_BASE..DM_BASE + region).step_by(WORDSIZE) {
+            match m.read(addr, DataTypeSize::Word, false) {
+                Ok(DataType::Word(w)) => assert_eq!(w, addr as u32),
+                other => panic!("Incorrect read @ {addr:#x}: {other:?}"),
+            }
+        }
+
+        m.print_summary();
+
+        assert_eq!(m.lower.len(), 2);
+        assert!(m.lower[0].stats().misses() > 0);
+        assert!(m.lower[1].stats().hits() > 0, "L3 holds the whole region");
+    }
+
+    #[test]
+    fn opt_beats_lru_on_a_loop() {
+        const DM_BASE: usize = 1 << 12;
+        const LINE: usize = 4 * WORDSIZE;
+
+        /* D-L1 hits over ten passes of a loop one line bigger than the cache */
+        let hits = |dm: CacheConfig| {
+            let mut m = Memory::new(&MemoryConfig {
+                main_bytes: 1 << 16,
+                im: CacheConfig::direct_mapped(1 << 8, 4),
+                dm,
+                dm_base: DM_BASE,
+                mmio_base: usize::MAX,
+                ..MemoryConfig::default()
+            }).unwrap();
+
+            let lines = dm.num_lines() + dm.num_sets();   // one line too many per set
+            let trace: Vec<usize> = (0..10).flat_map(|_| (0..lines).map(|i| DM_BASE + i * LINE)).collect();
+            m.set_future(&trace);
+            for &addr in &trace { m.read(addr, DataTypeSize::Word, false).unwrap(); }
+            (m.dm.stats().hits(), trace.len())
+        };
+
+        let fa = |policy| CacheConfig::fully_associative(1 << 8, 4, policy);
+        let sa = |policy| CacheConfig::set_associative(1 << 8, 4, 4, policy);
+
+        assert_eq!(hits(fa(EvictionPolicy::Lru)).0, 0, "LRU thrashes on a loop");
+        assert_eq!(hits(sa(EvictionPolicy::Lru)).0, 0, "LRU thrashes on a loop");
+
+        let (fa_opt, fa_total) = hits(fa(EvictionPolicy::Opt));
+        let (sa_opt, sa_total) = hits(sa(EvictionPolicy::Opt));
+        assert!(fa_opt * 2 > fa_total, "OPT hit only {fa_opt} of {fa_total}");
+        assert!(sa_opt * 2 > sa_total, "OPT hit only {sa_opt} of {sa_total}");
+    }
+
+    #[test]
+    fn amat_adds_up_the_levels_a_miss_walks() {
+        const DM_BASE: usize = 1 << 12;
+        let mut m = mem_l2(1 << 8, CacheConfig::direct_mapped(1 << 10, 4).with_latency(10, 5), DM_BASE);
+        let (a, conflict) = (DM_BASE, DM_BASE + (1 << 8));   // same L1 line, different L2 line
+
+        for addr in [a, a, conflict, a] {
+            m.read(addr, DataTypeSize::Word, false).unwrap();
+        }
+
+        /* 1+115, 1, 1+115, 1+10 */
+        assert_eq!(m.dm.stats().cycles(), 244);
+        assert!((m.stats.amat() - 61.0).abs() < EPSILON);
+        assert!((m.lower[0].stats().amat() - 80.0).abs() < EPSILON);
+        assert!((m.main.stats().amat() - 100.0).abs() < EPSILON);
+    }
+
+    /* D-L1 with the given write policies straight over main memory */
+    fn mem_writes(write_hit: WriteHit, write_miss: WriteMiss) -> Memory {
+        Memory::new(&MemoryConfig {
+            main_bytes: 1 << 16,
+            im: CacheConfig::direct_mapped(1 << 8, 4),
+            dm: CacheConfig::direct_mapped(1 << 8, 4).with_writes(write_hit, write_miss),
+            dm_base: 1 << 12,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        }).expect("valid test hierarchy")
+    }
+
+    #[test]
+    fn write_through_reaches_main() {
+        let mut m = mem_writes(WriteHit::Through, WriteMiss::Allocate);
+        let (a, conflict) = (0x1010, 0x1110);   // same D-L1 line index
+
+        m.write(DataType::Word(0xcafebabe), a, false).unwrap();
+        m.write(DataType::Word(0x1234), a + 4, false).unwrap();
+        assert!(matches!(m.main.read(a, DataTypeSize::Word, false), Ok(DataType::Word(0xcafebabe))));
+        assert!(!m.dm.is_line_dirty(conflict), "write-through lines stay clean");
+
+        m.read(conflict, DataTypeSize::Word, false).unwrap();
+        assert_eq!(m.main.stats().writes(), 2);
+        assert_eq!(m.main.stats().writebacks(), 0, "nothing left to write back");
+        assert_eq!(m.main.stats().bytes_written(), 8);
+    }
+
+    #[test]
+    fn write_around_skips_allocation() {
+        let mut m = mem_writes(WriteHit::Back, WriteMiss::Around);
+        let a = 0x1010;
+
+        m.write(DataType::Word(0xcafebabe), a, false).unwrap();
+        assert_eq!(m.dm.stats().misses(), 1);
+        assert_eq!(m.main.stats().writes(), 1);
+
+        /* the line was never brought in, so the read misses and fetches the new data */
+        match m.read(a, DataTypeSize::Word, false) {
+            Ok(w) => assert_eq!(w, DataType::Word(0xcafebabe)),
+            Err(e) => panic!("[MEMORY] read error: {e:?}"),
+        }
+        assert_eq!(m.dm.stats().misses(), 2);
+    }
+
+    #[test]
+    fn write_back_defers_traffic() {
+        let mut m = mem_writes(WriteHit::Back, WriteMiss::Allocate);
+        let (a, conflict) = (0x1010, 0x1110);
+
+        for i in 0..4 { m.write(DataType::Word(i), a, false).unwrap(); }
+        assert_eq!(m.main.stats().bytes_written(), 0);
+
+        m.read(conflict, DataTypeSize::Word, false).unwrap();
+        assert_eq!(m.main.stats().writebacks(), 1);
+        assert_eq!(m.main.stats().bytes_written(), 4 * WORDSIZE);
+        assert_eq!(m.dm.stats().writes(), 4);
+    }
+
+    #[test]
+    fn stats_split_reads_writes_and_evictions() {
+        let mut m = mem_writes(WriteHit::Back, WriteMiss::Allocate);
+        let (a, conflict) = (0x1010, 0x1110);
+        let line = 4 * WORDSIZE;
+
+        m.write(DataType::Word(1), a, false).unwrap();
+        m.read(conflict, DataTypeSize::Word, false).unwrap();
+        m.read(conflict, DataTypeSize::Word, false).unwrap();
+
+        let dm = m.dm.stats();
+        assert_eq!((dm.read_hits(), dm.read_misses(), dm.write_hits(), dm.write_misses()), (1, 1, 0, 1));
+        assert_eq!((dm.fills(), dm.clean_evictions(), dm.dirty_writebacks()), (2, 0, 1));
+        assert_eq!(dm.bytes_in(), 2 * line + WORDSIZE);
+        assert_eq!(dm.bytes_out(), line + 2 * WORDSIZE);
+
+        let main = m.main.stats();
+        assert_eq!((main.read_hits(), main.write_hits(), main.misses()), (2, 1, 0));
+        assert_eq!((main.bytes_in(), main.bytes_out()), (line, 2 * line));
+        assert_eq!(m.stats.bytes_out(), 2 * WORDSIZE);
+    }
+
+    #[test]
+    fn write_buffer_coalesces_write_through() {
+        let mut m = mem_writes(WriteHit::Through, WriteMiss::Allocate);
+        m.write_buffer = Some(WriteBuffer::new(&WriteBufferConfig::new(4), 4 * WORDSIZE));
+        let (a, conflict) = (0x1010, 0x1110);
+
+        for i in 0..4 { m.write(DataType::Word(i), a + 4 * i as usize, false).unwrap(); }
+        let wb = m.write_buffer.as_ref().unwrap();
+        assert_eq!((wb.occupancy(), wb.stats().merges()), (1, 3));
+        assert_eq!(m.main.stats().bytes_written(), 0);
+
+        /* evicted from the L1 before memory saw it: the refetch is forwarded */
+        m.read(conflict, DataTypeSize::Word, false).unwrap();
+        assert!(matches!(m.read(a + 8, DataTypeSize::Word, false), Ok(DataType::Word(2))));
+        assert_eq!(m.write_buffer.as_ref().unwrap().stats().forwards(), 1);
+
+        m.drain_write_buffer();
+        assert_eq!(m.main.stats().writebacks(), 1);
+        assert_eq!(m.main.stats().bytes_written(), 4 * WORDSIZE);
+    }
+
+    #[test]
+    fn errors_name_the_access_and_level() {
+        let mut m = mem(1 << 12, 1 << 10, 4, 1 << 10, 4, 1 << 10);
+
+        let e = m.read(0x402, DataTypeSize::Word, false).unwrap_err();
+        assert_eq!(e, MemoryError::new(MemoryErrorKind::NotAligned, 0x402, 4, "l1d"));
+        assert_eq!(e.to_string(), "l1d: 4-byte access at 0x402 is misaligned");
+
+        let e = m.main.read(0xffe, DataTypeSize::Word, false).unwrap_err();
+        assert_eq!(e.to_string(), "main: 4-byte access at 0xffe is misaligned");
+        let e = m.main.read(1 << 12, DataTypeSize::Byte, false).unwrap_err();
+        assert_eq!(e.to_string(), "main: 1-byte access at 0x1000 runs past the end of memory");
+
+        /* cached accesses are refused too, not served from a made-up line */
+        let e = m.write(DataType::Word(1), 1 << 12, false).unwrap_err();
+        assert_eq!(e, MemoryError::new(MemoryErrorKind::OutOfBounds, 1 << 12, 4, "main"));
+        let e = m.read(usize::MAX - 7, DataTypeSize::DoubleWord, false).unwrap_err();
+        assert_eq!(e.kind, MemoryErrorKind::OutOfBounds);
+
+        /* a miss is not an error: the access is served from below */
+        assert!(m.dm.read(0x400, DataTypeSize::Word, false).is_miss());
+        assert!(m.read(0x400, DataTypeSize::Word, false).is_ok());
+    }
+
+    #[test]
+    fn main_memory_is_backed_only_where_it_is_used() {
+        /* a 1 TiB address space, as sparse as a process's */
+        let mut m = mem(1 << 40, 1 << 10, 4, 1 << 10, 4, 1 << 10);
+        let stack = 0x7_ff00_0398;
+
+        m.write(DataType::DoubleWord(0x1234_5678_9abc_def0), stack, false).unwrap();
+        m.write(DataType::DoubleWord(7), stack + (1 << 30), false).unwrap();   // evicts the first line
+        assert_eq!(m.read(stack, DataTypeSize::DoubleWord, false), Ok(DataType::DoubleWord(0x1234_5678_9abc_def0)));
+        assert_eq!(m.read(stack + 8, DataTypeSize::Word, false), Ok(DataType::Word(0)));
+        assert_eq!(m.main.stats().writebacks(), 2);   // each line evicted the other once
+    }
+
+    #[test]
+    fn default_hierarchy_serves_its_data_region() {
+        let config = MemoryConfig::default();
+        let mut m = Memory::new(&config).unwrap();
+
+        m.read(config.dm_base, DataTypeSize::Word, false).unwrap();
+        m.write(DataType::Word(7), config.dm_base + 4, false).unwrap();
+        assert_eq!(m.read(config.dm_base + 4, DataTypeSize::Word, false), Ok(DataType::Word(7)));
+        assert_eq!((m.dm.stats().misses(), m.dm.stats().hits()), (1, 2));
+    }
+
+    #[test]
+    fn default_hierarchy_caches_a_whole_process() {
+        /* text, heap and stack of a lackey trace of a 64-bit binary */
+        let mut m = Memory::new(&MemoryConfig { routing: Some(Routing::Kind), ..MemoryConfig::default() }).unwrap();
+
+        m.fetch(0x0400_d7d4, DataTypeSize::Word, false).unwrap();
+        m.read(0x7_ff00_0398, DataTypeSize::DoubleWord, false).unwrap();
+        m.write(DataType::Word(0), 0x0422_1000, false).unwrap();
+        assert_eq!(m.im.stats().total_accesses(), 1);
+        assert_eq!(m.dm.stats().total_accesses(), 2);
+    }
+
     /* TESTS
      * im single access
      * dm single access
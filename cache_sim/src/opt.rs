@@ -0,0 +1,58 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/* ---------------- Belady OPT oracle ---------------- */
+/* every access index at which the trace touches each line, so a cache can
+ * ask which of its lines is next needed furthest in the future. `now` is
+ * shared with the Memory that issues the trace, which moves it forward once
+ * per access */
+
+pub const NEVER: usize = usize::MAX;
+
+#[derive(Debug)]
+pub struct NextUse {
+    now:  Rc<Cell<usize>>,
+    line_shift: u32,
+    uses: HashMap<usize, Vec<usize>>,   // line number -> ascending access indices
+}
+
+impl NextUse {
+    pub fn new(trace: &[usize], line_bytes: usize, now: Rc<Cell<usize>>) -> Self {
+        let line_shift = line_bytes.trailing_zeros();
+        let mut uses: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, &addr) in trace.iter().enumerate() {
+            uses.entry(addr >> line_shift).or_default().push(i);
+        }
+        Self { now, line_shift, uses }
+    }
+
+    /* first access after the current one that touches addr's line */
+    pub fn next_use(&self, addr: usize) -> usize {
+        let now = self.now.get();
+        self.uses.get(&(addr >> self.line_shift))
+            .and_then(|at| at.get(at.partition_point(|&i| i <= now)).copied())
+            .unwrap_or(NEVER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_use_tracks_now() {
+        let now = Rc::new(Cell::new(0));
+        let trace = [0x00, 0x40, 0x04, 0x80, 0x44];
+        let oracle = NextUse::new(&trace, 16, Rc::clone(&now));
+
+        assert_eq!(oracle.next_use(0x0c), 2);       // same 16-byte line as 0x00
+        assert_eq!(oracle.next_use(0x40), 1);
+        assert_eq!(oracle.next_use(0xc0), NEVER);   // never touched
+
+        now.set(2);
+        assert_eq!(oracle.next_use(0x00), NEVER);
+        assert_eq!(oracle.next_use(0x40), 4);
+        assert_eq!(oracle.next_use(0x80), 3);
+    }
+}
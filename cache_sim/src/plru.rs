@@ -0,0 +1,77 @@
+/* ---------------- tree pseudo-LRU ---------------- */
+/* one binary tree of ways-1 bits per set, stored heap-style (children of
+ * node n are 2n+1 and 2n+2); each bit points at the half holding the
+ * pseudo-LRU line: false = left, true = right */
+
+#[derive(Debug, Clone)]
+pub struct TreePlru {
+    ways: usize,
+    bits: Vec<bool>,    // sets × (ways - 1)
+}
+
+impl TreePlru {
+    pub fn new(sets: usize, ways: usize) -> Self {
+        assert!(ways.is_power_of_two(), "tree PLRU needs a power-of-two number of ways");
+        Self { ways, bits: vec![false; sets * (ways - 1)] }
+    }
+
+    #[inline(always)] fn levels(&self) -> u32 { self.ways.trailing_zeros() }
+
+    fn tree(&mut self, set: usize) -> &mut [bool] {
+        let n = self.ways - 1;
+        &mut self.bits[set * n..(set + 1) * n]
+    }
+
+    /* point every node on the path to `way` away from it */
+    pub fn touch(&mut self, set: usize, way: usize) {
+        let levels = self.levels();
+        let tree = self.tree(set);
+        let mut node = 0;
+        for level in (0..levels).rev() {
+            let right = (way >> level) & 1 == 1;
+            tree[node] = !right;
+            node = 2 * node + 1 + right as usize;
+        }
+    }
+
+    /* follow the bits down to the pseudo-LRU way */
+    pub fn victim(&self, set: usize) -> usize {
+        let n = self.ways - 1;
+        let tree = &self.bits[set * n..(set + 1) * n];
+        let (mut node, mut way) = (0, 0);
+        for _ in 0..self.levels() {
+            let right = tree[node];
+            way = (way << 1) | right as usize;
+            node = 2 * node + 1 + right as usize;
+        }
+        way
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn victim_avoids_recent_ways() {
+        let mut t = TreePlru::new(1, 4);
+        for way in 0..4 { t.touch(0, way); }
+        assert_eq!(t.victim(0), 0);     // 0 is in the half touched least recently
+
+        t.touch(0, 0);
+        assert_eq!(t.victim(0), 2);     // left half just used, right half's older way 2
+        t.touch(0, 2);
+        assert_eq!(t.victim(0), 1);
+    }
+
+    #[test]
+    fn sets_are_independent() {
+        let mut t = TreePlru::new(2, 8);
+        t.touch(0, 0);
+        assert_eq!(t.victim(0), 4);
+        assert_eq!(t.victim(1), 0);
+
+        let single = TreePlru::new(3, 1);
+        assert_eq!(single.victim(2), 0);
+    }
+}
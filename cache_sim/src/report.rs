@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::cache::CacheConfig;
+use crate::mem_stats::MemStats;
+use crate::memory::MemoryConfig;
+use crate::write_buffer::WriteBufferStats;
+
+/* ---------------- machine-readable results ---------------- */
+/* a borrowed snapshot of a finished run: the hierarchy it ran on and the
+ * counters of every level, written out as JSON or CSV for sweep scripts */
+
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub config: &'a MemoryConfig,
+    pub levels: Vec<LevelReport<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_buffer: Option<WriteBufferReport<'a>>,
+}
+
+/// One row: the whole hierarchy (`memory`), a cache level, or `main`.
+#[derive(Debug, Serialize)]
+pub struct LevelReport<'a> {
+    pub level: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<&'a CacheConfig>,
+    pub accesses: usize,
+    pub hit_rate: f64,
+    pub miss_rate: f64,
+    pub amat: f64,
+    #[serde(flatten)]
+    pub stats: &'a MemStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WriteBufferReport<'a> {
+    pub held: usize,
+    pub avg_occupancy: f64,
+    #[serde(flatten)]
+    pub stats: &'a WriteBufferStats,
+}
+
+impl<'a> LevelReport<'a> {
+    pub fn new(level: impl Into<String>, cache: Option<&'a CacheConfig>, stats: &'a MemStats) -> Self {
+        Self {
+            level: level.into(),
+            cache,
+            accesses: stats.total_accesses(),
+            hit_rate: stats.hit_rate(),
+            miss_rate: stats.miss_rate(),
+            amat: stats.amat(),
+            stats,
+        }
+    }
+}
+
+impl Report<'_> {
+    /* counters first so existing columns keep their place; the geometry
+     * columns stay empty for the memory and main rows */
+    pub const CSV_HEADER: &'static str =
+        "bytes,words_per_line,assoc,policy,write_hit,write_miss,hit_latency,miss_penalty";
+
+    pub fn write_json(&self, out: &mut impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, self)?;
+        writeln!(out)
+    }
+
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{},{}", MemStats::CSV_HEADER, Self::CSV_HEADER)?;
+        for row in &self.levels {
+            let cache = row.cache.map_or_else(|| ",,,,,,,".to_string(), |c| {
+                let assoc = if c.is_fully_associative() { "full".to_string() } else { c.assoc.to_string() };
+                format!("{},{},{assoc},{},{},{},{},{}", c.bytes, c.words_per_line, c.policy,
+                    c.write_hit, c.write_miss, c.hit_latency, c.miss_penalty)
+            });
+            writeln!(out, "{},{cache}", row.stats.csv_row(&row.level))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{DataType, DataTypeSize, Memory, MemoryAccess};
+
+    fn run() -> Memory {
+        let mut m = Memory::new(&MemoryConfig {
+            main_bytes: 1 << 16,
+            im: CacheConfig::direct_mapped(1 << 8, 4),
+            dm: CacheConfig::fully_associative(1 << 8, 4, crate::cache::EvictionPolicy::Lru),
+            dm_base: 1 << 12,
+            mmio_base: usize::MAX,
+            ..MemoryConfig::default()
+        }).expect("valid test hierarchy");
+        m.write(DataType::Word(7), 0x1000, false).unwrap();
+        m.read(0x1000, DataTypeSize::Word, false).unwrap();
+        m
+    }
+
+    #[test]
+    fn json_round_trips_config_and_counters() {
+        let m = run();
+        let mut out = Vec::new();
+        m.report().write_json(&mut out).unwrap();
+
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let config: MemoryConfig = serde_json::from_value(v["config"].clone()).unwrap();
+        assert_eq!(&config, m.config());
+
+        let l1d = &v["levels"][2];
+        assert_eq!(l1d["level"], "l1d");
+        assert_eq!(l1d["cache"]["assoc"], "full");
+        assert_eq!((l1d["write_misses"].as_u64(), l1d["read_hits"].as_u64()), (Some(1), Some(1)));
+        assert!(v["levels"][0]["cache"].is_null());
+        assert_eq!(v["levels"][1]["miss_rate"].as_f64(), Some(0.0), "l1i saw no accesses");
+    }
+
+    #[test]
+    fn csv_rows_line_up_with_the_header() {
+        let mut out = Vec::new();
+        run().report().write_csv(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<&str>> = text.lines().map(|l| l.split(',').collect()).collect();
+
+        assert_eq!(rows.len(), 1 + 4);
+        assert!(rows.iter().all(|r| r.len() == rows[0].len()));
+        let assoc = rows[0].iter().position(|&c| c == "assoc").unwrap();
+        assert_eq!((rows[3][0], rows[3][assoc]), ("l1d", "full"));
+        assert_eq!(rows[4][assoc], "");
+    }
+}
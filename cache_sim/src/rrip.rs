@@ -0,0 +1,134 @@
+use rand::Rng;
+
+use crate::cache::EvictionPolicy;
+
+/* ---------------- re-reference interval prediction ---------------- */
+/* a 2-bit RRPV per line: 0 = re-referenced soon, RRPV_MAX = distant.
+ * hits promote to 0, victims are lines at RRPV_MAX (the set ages until
+ * one is), and the policies differ only in where a fill is inserted:
+ *   SRRIP  always at RRPV_MAX - 1
+ *   BRRIP  at RRPV_MAX, and at RRPV_MAX - 1 once every BRRIP_LONG_ODDS fills
+ *   DRRIP  leader sets run one each; PSEL picks for the followers */
+
+pub const RRPV_MAX: u8 = 3;
+const BRRIP_LONG_ODDS: u32 = 32;
+
+const LEADER_PERIOD: usize = 32;     // one SRRIP and one BRRIP leader per 32 sets
+const PSEL_MAX: u16 = (1 << 10) - 1; // 10-bit saturating counter
+
+#[derive(Debug, Clone)]
+pub struct Rrip {
+    ways:   usize,
+    rrpv:   Vec<u8>,    // sets × ways, set-major
+    period: usize,
+    psel:   u16,        // above the midpoint: SRRIP leaders miss more, followers use BRRIP
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Leader { Srrip, Brrip, Follower }
+
+impl Rrip {
+    pub fn new(sets: usize, ways: usize) -> Self {
+        Self {
+            ways,
+            rrpv: vec![RRPV_MAX; sets * ways],
+            period: LEADER_PERIOD.min(sets),
+            psel: PSEL_MAX / 2,
+        }
+    }
+
+    fn set(&self, set: usize) -> &[u8] {
+        &self.rrpv[set * self.ways..(set + 1) * self.ways]
+    }
+
+    fn leader(&self, set: usize) -> Leader {
+        match set % self.period {
+            0                                       => Leader::Srrip,
+            n if n == self.period - 1               => Leader::Brrip,
+            _                                       => Leader::Follower,
+        }
+    }
+
+    /* first way that would reach RRPV_MAX once the set is aged */
+    pub fn victim(&self, set: usize) -> usize {
+        let rrpvs = self.set(set);
+        let oldest = rrpvs.iter().copied().max().unwrap_or(RRPV_MAX);
+        rrpvs.iter().position(|&r| r == oldest).unwrap_or(0)
+    }
+
+    pub fn hit(&mut self, set: usize, way: usize) {
+        self.rrpv[set * self.ways + way] = 0;
+    }
+
+    /* leader-set misses steer PSEL for DRRIP */
+    pub fn record_miss(&mut self, set: usize) {
+        match self.leader(set) {
+            Leader::Srrip    => self.psel = (self.psel + 1).min(PSEL_MAX),
+            Leader::Brrip    => self.psel = self.psel.saturating_sub(1),
+            Leader::Follower => {}
+        }
+    }
+
+    /* age the set so the victim sits at RRPV_MAX, then insert the new line */
+    pub fn fill(&mut self, set: usize, way: usize, policy: EvictionPolicy, rng: &mut impl Rng) {
+        let bimodal = match policy {
+            EvictionPolicy::Brrip => true,
+            EvictionPolicy::Drrip => match self.leader(set) {
+                Leader::Srrip    => false,
+                Leader::Brrip    => true,
+                Leader::Follower => self.psel > PSEL_MAX / 2,
+            },
+            _ => false,
+        };
+        let insert = if bimodal && !rng.random_ratio(1, BRRIP_LONG_ODDS) { RRPV_MAX } else { RRPV_MAX - 1 };
+
+        let ways = self.ways;
+        let rrpvs = &mut self.rrpv[set * ways..(set + 1) * ways];
+        let age = RRPV_MAX - rrpvs.iter().copied().max().unwrap_or(RRPV_MAX);
+        rrpvs.iter_mut().for_each(|r| *r += age);
+        rrpvs[way] = insert;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn srrip_scan_resistance() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut r = Rrip::new(1, 4);
+        for way in 0..4 { r.fill(0, way, EvictionPolicy::Srrip, &mut rng); }
+        r.hit(0, 1);
+
+        /* the hit line outlives 6 one-shot fills (true LRU would keep it for 3) */
+        for _ in 0..6 {
+            let victim = r.victim(0);
+            assert_ne!(victim, 1, "the re-referenced line survives the scan");
+            r.fill(0, victim, EvictionPolicy::Srrip, &mut rng);
+        }
+    }
+
+    #[test]
+    fn psel_follows_leader_misses() {
+        let mut r = Rrip::new(64, 4);
+        assert_eq!(r.leader(0), Leader::Srrip);
+        assert_eq!(r.leader(31), Leader::Brrip);
+        assert_eq!(r.leader(5), Leader::Follower);
+
+        let start = r.psel;
+        for _ in 0..10 { r.record_miss(32); }
+        assert_eq!(r.psel, start + 10);
+        for _ in 0..4 { r.record_miss(63); r.record_miss(7); }
+        assert_eq!(r.psel, start + 6);
+
+        /* followers now insert bimodally: almost always at RRPV_MAX */
+        let mut rng = StdRng::seed_from_u64(1);
+        let distant = (0..64).filter(|_| {
+            r.fill(5, 0, EvictionPolicy::Drrip, &mut rng);
+            r.set(5)[0] == RRPV_MAX
+        }).count();
+        assert!(distant > 48, "only {distant} of 64 follower fills were distant");
+    }
+}
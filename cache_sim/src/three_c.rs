@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/* ---------------- 3C miss classification ---------------- */
+/* every reference of a cache is replayed into a shadow fully-associative
+ * LRU cache with the same number of lines; a real miss on a line never
+ * seen before is compulsory, one the shadow also misses is capacity, and
+ * one the shadow hits only happened because of the mapping: conflict */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissKind {
+    Compulsory,
+    Capacity,
+    Conflict,
+}
+
+#[derive(Debug)]
+pub struct MissClassifier {
+    line_shift: u32,
+    lines: usize,                       // shadow capacity
+    seen: HashSet<usize>,               // every line ever referenced
+    stamps: HashMap<usize, u64>,        // line -> last use, for lines in the shadow
+    by_age: BTreeMap<u64, usize>,       // last use -> line, oldest first
+    clock: u64,
+}
+
+impl MissClassifier {
+    pub fn new(lines: usize, line_bytes: usize) -> Self {
+        assert!(line_bytes.is_power_of_two(), "line size must be a power of two");
+        Self {
+            line_shift: line_bytes.trailing_zeros(),
+            lines,
+            seen: HashSet::new(),
+            stamps: HashMap::with_capacity(lines + 1),
+            by_age: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /* reference the line holding addr in the shadow; true on a shadow hit */
+    pub fn touch(&mut self, addr: usize) -> bool {
+        let line = addr >> self.line_shift;
+        self.clock += 1;
+        self.seen.insert(line);
+
+        let hit = match self.stamps.insert(line, self.clock) {
+            Some(old) => {
+                self.by_age.remove(&old);
+                true
+            }
+            None => false,
+        };
+        self.by_age.insert(self.clock, line);
+
+        if self.stamps.len() > self.lines {
+            let (_, lru) = self.by_age.pop_first().expect("shadow holds a line");
+            self.stamps.remove(&lru);
+        }
+        hit
+    }
+
+    /* reference addr after it missed in the real cache and name the miss */
+    pub fn classify(&mut self, addr: usize) -> MissKind {
+        let first = !self.seen.contains(&(addr >> self.line_shift));
+        let shadow_hit = self.touch(addr);
+
+        if first {
+            MissKind::Compulsory
+        } else if shadow_hit {
+            MissKind::Conflict
+        } else {
+            MissKind::Capacity
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_pong_is_conflict_and_sweep_is_capacity() {
+        let mut c = MissClassifier::new(2, 16);
+        assert_eq!(c.classify(0x00), MissKind::Compulsory);
+        assert_eq!(c.classify(0x40), MissKind::Compulsory);
+        /* both lines fit the shadow: a real miss here is the mapping's fault */
+        assert_eq!(c.classify(0x00), MissKind::Conflict);
+
+        assert_eq!(c.classify(0x80), MissKind::Compulsory);   // shadow drops 0x40
+        assert_eq!(c.classify(0x40), MissKind::Capacity);
+    }
+}
@@ -0,0 +1,223 @@
+use std::io::{self, Read, Write};
+
+use super::{size_of, store_of, Position, Trace, TraceError, TraceOp, TraceRecord};
+
+/* ---------------- binary trace format, version 1 ----------------
+ *
+ *   header  "CSTR" version:u8
+ *   record  tag:u8  addr:varint  [value:size bytes, LE]  [pc:varint]
+ *
+ *   tag     bits 0-1  op: 0 read, 1 write, 2 fetch
+ *           bits 2-3  log2 of the access size (1/2/4/8 bytes)
+ *           bit  4    a value follows (writes; left out when zero)
+ *           bit  5    a PC follows
+ *           bits 6-7  reserved: written 0, ignored on read
+ *
+ * addresses are the zigzagged difference from the previous record's, so a
+ * sequential stream costs a byte or two per address; varints are LEB128 */
+
+pub const MAGIC: &[u8; 4] = b"CSTR";
+pub const VERSION: u8 = 1;
+
+const OP_MASK: u8    = 0b0000_0011;
+const SIZE_SHIFT: u8 = 2;
+const HAS_VALUE: u8  = 1 << 4;
+const HAS_PC: u8     = 1 << 5;
+
+/// Streams `TraceRecord`s out in the binary format.
+pub struct BinaryWriter<W: Write> {
+    out: W,
+    prev_addr: usize,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        Ok(Self { out, prev_addr: 0 })
+    }
+
+    pub fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let (op, size, value) = match &record.op {
+            TraceOp::Read(size)  => (0, size.clone().size(), None),
+            TraceOp::Write(data) => (1, data.payload_size(), Some(data.to_le_bytes())),
+            TraceOp::Fetch(size) => (2, size.clone().size(), None),
+        };
+        let value = value.filter(|bytes| bytes.iter().any(|&b| b != 0));
+
+        let mut tag = op | (size.trailing_zeros() as u8) << SIZE_SHIFT;
+        if value.is_some()      { tag |= HAS_VALUE; }
+        if record.pc.is_some()  { tag |= HAS_PC; }
+        self.out.write_all(&[tag])?;
+
+        let delta = record.addr.wrapping_sub(self.prev_addr) as i64;
+        write_varint(&mut self.out, ((delta << 1) ^ (delta >> 63)) as u64)?;
+        self.prev_addr = record.addr;
+
+        if let Some(bytes) = value {
+            self.out.write_all(&bytes)?;
+        }
+        if let Some(pc) = record.pc {
+            write_varint(&mut self.out, pc as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Flush and hand back the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Streams the records of a binary trace. The format cannot resync after a
+/// bad record, so the first error ends the stream.
+pub struct BinaryReader<R: Read> {
+    input: R,
+    prev_addr: usize,
+    record_no: usize,
+    failed: bool,
+}
+
+impl<R: Read> BinaryReader<R> {
+    /// Check the header; InvalidData if it is not a trace this build reads.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a binary trace"));
+        }
+        if header[4] != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("binary trace version {} (this build reads {VERSION})", header[4])));
+        }
+        Ok(Self { input, prev_addr: 0, record_no: 0, failed: false })
+    }
+
+    /* Ok(None) at a clean end of file, between records */
+    fn record(&mut self) -> Result<Option<TraceRecord>, String> {
+        let mut tag = [0u8];
+        match self.input.read(&mut tag) {
+            Ok(0)  => return Ok(None),
+            Ok(_)  => {}
+            Err(e) => return Err(e.to_string()),
+        }
+        let tag = tag[0];
+
+        let bytes = 1 << ((tag >> SIZE_SHIFT) & 0b11);
+        let zigzag = read_varint(&mut self.input)?;
+        let delta = ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64);
+        let addr = self.prev_addr.wrapping_add(delta as usize);
+        self.prev_addr = addr;
+
+        let mut raw = 0;
+        if tag & HAS_VALUE != 0 {
+            let mut value = [0u8; 8];
+            self.input.read_exact(&mut value[..bytes]).map_err(|_| "truncated value".to_string())?;
+            raw = u64::from_le_bytes(value);
+        }
+        let pc = if tag & HAS_PC != 0 { Some(read_varint(&mut self.input)? as usize) } else { None };
+
+        let op = match tag & OP_MASK {
+            0 => TraceOp::Read(size_of(bytes)?),
+            1 => TraceOp::Write(store_of(bytes, raw)?),
+            2 => TraceOp::Fetch(size_of(bytes)?),
+            _ => return Err(format!("bad op in tag {tag:#04x}")),
+        };
+        Ok(Some(TraceRecord { addr, op, pc }))
+    }
+}
+
+impl<R: Read> Iterator for BinaryReader<R> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed { return None; }
+        self.record_no += 1;
+        match self.record() {
+            Ok(record) => record.map(Ok),
+            Err(reason) => {
+                self.failed = true;
+                Some(Err(TraceError::parse(self.pos(), reason)))
+            }
+        }
+    }
+}
+
+impl<R: Read> Trace for BinaryReader<R> {
+    fn pos(&self) -> Position {
+        Position::Record(self.record_no)
+    }
+}
+
+fn write_varint(out: &mut impl Write, mut v: u64) -> io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut n = 0;
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf[n] = byte;
+            n += 1;
+            break;
+        }
+        buf[n] = byte | 0x80;
+        n += 1;
+    }
+    out.write_all(&buf[..n])
+}
+
+fn read_varint(input: &mut impl Read) -> Result<u64, String> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        input.read_exact(&mut byte).map_err(|_| "truncated record".to_string())?;
+        v |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    Err("varint longer than 64 bits".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{DataType, DataTypeSize};
+
+    fn round_trip(records: &[TraceRecord]) -> (Vec<u8>, Vec<Result<TraceRecord, String>>) {
+        let mut w = BinaryWriter::new(Vec::new()).unwrap();
+        for r in records {
+            w.write(r).unwrap();
+        }
+        let bytes = w.finish().unwrap();
+        let back = BinaryReader::new(&bytes[..]).unwrap().map(|r| r.map_err(|e| e.to_string())).collect();
+        (bytes, back)
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let records = [
+            TraceRecord::new(0x0060_0000, TraceOp::Read(DataTypeSize::Word)),
+            TraceRecord::new(0x0060_0004, TraceOp::Write(DataType::Word(69))),
+            TraceRecord::new(0x0000_1000, TraceOp::Write(DataType::Byte(0))),
+            TraceRecord { pc: Some(0x40_0000), ..TraceRecord::new(0x40_0000, TraceOp::Fetch(DataTypeSize::DoubleWord)) },
+        ];
+        let (bytes, back) = round_trip(&records);
+        assert_eq!(back, records.map(Ok));
+        /* the +4 step costs a tag and one address byte, the value 4 more */
+        assert_eq!(bytes[5 + 1 + 4], 1 | 2 << SIZE_SHIFT | HAS_VALUE);
+        assert_eq!(bytes[5 + 1 + 4 + 1], 8);
+    }
+
+    #[test]
+    fn bad_header_and_truncation() {
+        assert!(BinaryReader::new(&b"CSTR\x09"[..]).is_err());
+        assert!(BinaryReader::new(&b"r 10 4"[..]).is_err());
+
+        let (mut bytes, _) = round_trip(&[TraceRecord::new(0x1234_5678, TraceOp::Read(DataTypeSize::Word))]);
+        bytes.pop();
+        let back: Vec<_> = BinaryReader::new(&bytes[..]).unwrap().map(|r| r.map_err(|e| e.to_string())).collect();
+        assert_eq!(back, [Err("record 1: truncated record".to_string())]);
+    }
+}
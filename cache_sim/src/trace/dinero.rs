@@ -0,0 +1,54 @@
+use std::collections::VecDeque;
+
+use super::{parse_hex, size_of, store_of, TraceOp, TraceRecord};
+
+/* Dinero "din": `<label> <hex addr> [size]`. Labels 0/1/2 are a data read,
+ * a data write and an instruction fetch; 3 (escape) and 4 (flush) carry no
+ * reference and are skipped. A missing size means a word; writes carry no
+ * value, so they store zero */
+const WORD: usize = 4;
+
+pub(super) fn parse(line: &str, out: &mut VecDeque<TraceRecord>) -> Result<(), String> {
+    let mut cols = line.split_ascii_whitespace();
+    let Some(label) = cols.next() else { return Ok(()) };
+    let Some(addr) = cols.next() else {
+        return Err(format!("label {label} without an address"));
+    };
+
+    let bytes = match cols.next() {
+        Some(size) => size.parse::<usize>().map_err(|_| format!("size must be decimal, got '{size}'"))?,
+        None       => WORD,
+    };
+
+    let op = match label {
+        "0" => TraceOp::Read(size_of(bytes)?),
+        "1" => TraceOp::Write(store_of(bytes, 0)?),
+        "2" => TraceOp::Fetch(size_of(bytes)?),
+        "3" | "4" => return Ok(()),
+        _ => return Err(format!("unknown dinero label '{label}'")),
+    };
+    out.push_back(TraceRecord::new(parse_hex(addr)?, op));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{DataType, DataTypeSize};
+
+    fn parse_one(line: &str) -> Result<Option<TraceRecord>, String> {
+        let mut out = VecDeque::new();
+        parse(line, &mut out)?;
+        Ok(out.pop_front())
+    }
+
+    #[test]
+    fn labels_map_to_ops() {
+        assert_eq!(parse_one("0 1000"), Ok(Some(TraceRecord::new(0x1000, TraceOp::Read(DataTypeSize::Word)))));
+        assert_eq!(parse_one("1 0x2000 2"), Ok(Some(TraceRecord::new(0x2000, TraceOp::Write(DataType::Halfword(0))))));
+        assert_eq!(parse_one("2 400 8"), Ok(Some(TraceRecord::new(0x400, TraceOp::Fetch(DataTypeSize::DoubleWord)))));
+        assert_eq!(parse_one("4 0"), Ok(None));
+        assert!(parse_one("7 400").is_err());
+        assert!(parse_one("0 zz").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::io;
+
+use crate::memory::{MemoryError, MemoryErrorKind};
+
+/// Where in a trace a record came from: a line of a text trace, or the
+/// index of a record in a binary one. Both count from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Line(usize),
+    Record(usize),
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Position::Line(n)   => write!(f, "line {n}"),
+            Position::Record(n) => write!(f, "record {n}"),
+        }
+    }
+}
+
+/// A record that could not be read, or that the hierarchy refused.
+#[derive(Debug)]
+pub struct TraceError {
+    pub pos: Position,
+    pub kind: TraceErrorKind,
+}
+
+#[derive(Debug)]
+pub enum TraceErrorKind {
+    /// the trace itself could not be read; nothing after it can be trusted
+    Io(io::Error),
+    /// a record that does not parse
+    Parse(String),
+    /// an access the hierarchy rejected
+    Access(MemoryError),
+}
+
+impl TraceError {
+    pub fn parse(pos: Position, reason: impl Into<String>) -> Self {
+        Self { pos, kind: TraceErrorKind::Parse(reason.into()) }
+    }
+
+    pub fn io(pos: Position, e: io::Error) -> Self {
+        Self { pos, kind: TraceErrorKind::Io(e) }
+    }
+
+    /* the access at pos failed with e */
+    pub fn access(pos: Position, e: MemoryError) -> Self {
+        Self { pos, kind: TraceErrorKind::Access(e) }
+    }
+
+    /// Reading cannot carry on past this one, whatever the mode.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self.kind, TraceErrorKind::Io(_))
+    }
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.pos)?;
+        match &self.kind {
+            TraceErrorKind::Io(e)         => write!(f, "{e}"),
+            TraceErrorKind::Parse(reason) => f.write_str(reason),
+            TraceErrorKind::Access(e)     => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            TraceErrorKind::Io(e)     => Some(e),
+            TraceErrorKind::Access(e) => Some(e),
+            TraceErrorKind::Parse(_)  => None,
+        }
+    }
+}
+
+/// Records a lenient run skipped, by reason.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Skipped {
+    pub unparsable: usize,
+    pub misaligned: usize,
+    pub rejected: usize,
+}
+
+impl Skipped {
+    pub fn count(&mut self, e: &TraceError) {
+        match &e.kind {
+            TraceErrorKind::Io(_)    => {}
+            TraceErrorKind::Parse(_) => self.unparsable += 1,
+            TraceErrorKind::Access(e) if e.kind == MemoryErrorKind::NotAligned => self.misaligned += 1,
+            TraceErrorKind::Access(_) => self.rejected += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.unparsable + self.misaligned + self.rejected
+    }
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped {} records ({} unparsable, {} misaligned, {} rejected)",
+            self.total(), self.unparsable, self.misaligned, self.rejected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn misaligned_accesses_are_told_apart_and_counted() {
+        let mut skipped = Skipped::default();
+        let errors = [
+            TraceError::parse(Position::Line(3), "bad hex address 'zz'"),
+            TraceError::access(Position::Line(7), MemoryError::new(MemoryErrorKind::NotAligned, 0x403, 4, "l1d")),
+            TraceError::access(Position::Record(9), MemoryError::new(MemoryErrorKind::OutOfBounds, 0x40_0000, 4, "main")),
+        ];
+        for e in &errors {
+            skipped.count(e);
+        }
+
+        assert_eq!(errors[0].to_string(), "line 3: bad hex address 'zz'");
+        assert_eq!(errors[1].to_string(), "line 7: l1d: 4-byte access at 0x403 is misaligned");
+        assert_eq!(errors[2].to_string(), "record 9: main: 4-byte access at 0x400000 runs past the end of memory");
+        assert_eq!(skipped, Skipped { unparsable: 1, misaligned: 1, rejected: 1 });
+    }
+}
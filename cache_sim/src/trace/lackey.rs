@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+
+use super::{parse_hex, size_of, store_of, TraceOp, TraceRecord};
+
+/* valgrind --tool=lackey --trace-mem=yes: `I  <hex addr>,<size>` at column 0
+ * for a fetch, ` L|S|M <hex addr>,<size>` indented for data; M is a load
+ * then a store. Lackey has no values, stores write zero; `==pid==` lines are
+ * valgrind's own banner */
+pub(super) fn parse(line: &str, out: &mut VecDeque<TraceRecord>) -> Result<(), String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("==") {
+        return Ok(());
+    }
+
+    let mut cols = line.split_ascii_whitespace();
+    let kind = cols.next().unwrap_or_default();
+    let Some((addr, bytes)) = cols.next().and_then(|field| field.split_once(',')) else {
+        return Err(format!("expected `{kind} <addr>,<size>`"));
+    };
+    let addr = parse_hex(addr)?;
+    let bytes = bytes.parse::<usize>().map_err(|_| format!("size must be decimal, got '{bytes}'"))?;
+    if bytes == 0 {
+        return Err("zero-sized access".into());
+    }
+    let end = addr.checked_add(bytes)
+        .ok_or_else(|| format!("{bytes}-byte access at {addr:#x} wraps past the top of memory"))?;
+
+    for (addr, n) in pieces(addr, end) {
+        match kind {
+            "I" => out.push_back(TraceRecord::new(addr, TraceOp::Fetch(size_of(n)?))),
+            "L" => out.push_back(TraceRecord::new(addr, TraceOp::Read(size_of(n)?))),
+            "S" => out.push_back(TraceRecord::new(addr, TraceOp::Write(store_of(n, 0)?))),
+            "M" => {
+                out.push_back(TraceRecord::new(addr, TraceOp::Read(size_of(n)?)));
+                out.push_back(TraceRecord::new(addr, TraceOp::Write(store_of(n, 0)?)));
+            }
+            _ => return Err(format!("unknown lackey op '{kind}'")),
+        }
+    }
+    Ok(())
+}
+
+/* instructions are any length from 1 byte up and vector loads reach 32, at
+ * any alignment; the caches only take naturally aligned 1/2/4/8-byte
+ * accesses, so cover [addr, end) with the fewest such pieces */
+fn pieces(mut addr: usize, end: usize) -> impl Iterator<Item = (usize, usize)> {
+    std::iter::from_fn(move || {
+        if addr >= end { return None; }
+        let mut n = 8;
+        while !addr.is_multiple_of(n) || n > end - addr {
+            n /= 2;
+        }
+        let piece = (addr, n);
+        addr += n;
+        Some(piece)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{DataType, DataTypeSize};
+
+    fn parse_all(line: &str) -> Vec<TraceRecord> {
+        let mut out = VecDeque::new();
+        parse(line, &mut out).unwrap();
+        out.into()
+    }
+
+    #[test]
+    fn modify_is_load_then_store() {
+        assert_eq!(parse_all("I  0400d7d4,4"),
+            [TraceRecord::new(0x0400_d7d4, TraceOp::Fetch(DataTypeSize::Word))]);
+        assert_eq!(parse_all(" S 7ff000398,8"),
+            [TraceRecord::new(0x7_ff00_0398, TraceOp::Write(DataType::DoubleWord(0)))]);
+        assert_eq!(parse_all(" M 0421d020,2"), [
+            TraceRecord::new(0x0421_d020, TraceOp::Read(DataTypeSize::Halfword)),
+            TraceRecord::new(0x0421_d020, TraceOp::Write(DataType::Halfword(0))),
+        ]);
+        assert!(parse_all("==4242== Lackey, an example Valgrind tool").is_empty());
+    }
+
+    #[test]
+    fn odd_sizes_split_into_aligned_pieces() {
+        assert_eq!(pieces(0x1003, 0x100a).collect::<Vec<_>>(), [(0x1003, 1), (0x1004, 4), (0x1008, 2)]);
+        assert_eq!(pieces(0x2000, 0x2010).collect::<Vec<_>>(), [(0x2000, 8), (0x2008, 8)]);
+    }
+
+    #[test]
+    fn accesses_at_the_top_of_memory_do_not_overflow() {
+        let mut out = VecDeque::new();
+        assert_eq!(parse(" L fffffffffffffffc,8", &mut out),
+            Err("8-byte access at 0xfffffffffffffffc wraps past the top of memory".into()));
+        assert!(out.is_empty());
+
+        assert_eq!(parse_all(" L fffffffffffffffe,1"),
+            [TraceRecord::new(usize::MAX - 1, TraceOp::Read(DataTypeSize::Byte))]);
+    }
+}
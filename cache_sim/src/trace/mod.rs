@@ -0,0 +1,379 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::memory::{DataType, DataTypeSize, Routing};
+
+mod binary;
+mod dinero;
+mod error;
+mod lackey;
+mod text;
+
+pub use binary::BinaryWriter;
+pub use error::{Position, Skipped, TraceError};
+
+/* ── trace records ───────────────────────────────────────────────────── */
+
+/// One memory reference, whatever format it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceOp {
+    Read(DataTypeSize),
+    Write(DataType),
+    /// instruction fetch
+    Fetch(DataTypeSize),
+}
+
+impl TraceOp {
+    /// Bytes the access covers.
+    pub fn bytes(&self) -> usize {
+        match self {
+            TraceOp::Read(size) | TraceOp::Fetch(size) => size.clone().size(),
+            TraceOp::Write(data) => data.payload_size(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    pub addr: usize,
+    pub op: TraceOp,
+    /// the instruction that made the access, where the trace records it
+    pub pc: Option<usize>,
+}
+
+impl TraceRecord {
+    pub fn new(addr: usize, op: TraceOp) -> Self {
+        Self { addr, op, pc: None }
+    }
+}
+
+/// A stream of records that knows where the last one came from.
+pub trait Trace: Iterator<Item = Result<TraceRecord, TraceError>> {
+    fn pos(&self) -> Position;
+
+    /// The routing a hierarchy should use when none is configured: `Kind`
+    /// for a trace that marks its instruction fetches.
+    fn routing(&self) -> Routing {
+        Routing::Kind
+    }
+}
+
+/// Any trace, whatever its format.
+pub type Records = Box<dyn Trace>;
+
+/* ── formats ─────────────────────────────────────────────────────────── */
+
+/// Which reader decodes a trace; `Auto` sniffs the first record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Auto,
+    /// `r|w|i <hex addr> <size> [value]`
+    Text,
+    /// Dinero "din": `<label> <hex addr> [size]`, label 0 read, 1 write, 2 ifetch
+    Dinero,
+    /// `valgrind --tool=lackey --trace-mem=yes`: `I|L|S|M <hex addr>,<size>`
+    Lackey,
+    /// the versioned binary format of `--convert`, see binary.rs
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto"            => Ok(TraceFormat::Auto),
+            "text"            => Ok(TraceFormat::Text),
+            "dinero" | "din"  => Ok(TraceFormat::Dinero),
+            "lackey"          => Ok(TraceFormat::Lackey),
+            "binary" | "bin"  => Ok(TraceFormat::Binary),
+            _ => Err(format!("unknown trace format '{s}' (expected auto, text, dinero, lackey or binary)")),
+        }
+    }
+}
+
+impl fmt::Display for TraceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TraceFormat::Auto   => "auto",
+            TraceFormat::Text   => "text",
+            TraceFormat::Dinero => "dinero",
+            TraceFormat::Lackey => "lackey",
+            TraceFormat::Binary => "binary",
+        })
+    }
+}
+
+impl TraceFormat {
+    /* a binary trace opens with its magic and a .din file is Dinero;
+     * otherwise the first record gives it away: a leading digit is a Dinero
+     * label, `addr,size` is Lackey (after its `==pid==` banner), and the text
+     * format starts with its r/w/i op */
+    fn detect(path: &Path, head: &[u8]) -> Self {
+        if head.starts_with(binary::MAGIC) {
+            return TraceFormat::Binary;
+        }
+        if path.extension().is_some_and(|ext| ext == "din") {
+            return TraceFormat::Dinero;
+        }
+        let first = head.split(|&b| b == b'\n')
+            .map(|line| line.trim_ascii())
+            .find(|line| !line.is_empty() && !line.starts_with(b"=="));
+        match first {
+            Some(line) if line[0].is_ascii_digit() => TraceFormat::Dinero,
+            Some(line) if b"ILSM".contains(&line[0]) && line.contains(&b',') => TraceFormat::Lackey,
+            _ => TraceFormat::Text,
+        }
+    }
+
+    /* the r/w text format predates `i` records, so a text trace is routed
+     * by address unless its first HEAD_BYTES show it marks its fetches; the other
+     * formats always do */
+    fn routing(self, head: &[u8]) -> Routing {
+        let fetches = head.split(|&b| b == b'\n').any(|line| line.trim_ascii_start().starts_with(b"i "));
+        match self {
+            TraceFormat::Text if !fetches => Routing::Address,
+            _ => Routing::Kind,
+        }
+    }
+
+    fn parser(self) -> ParseFn {
+        match self {
+            TraceFormat::Auto | TraceFormat::Binary => unreachable!("not line based"),
+            TraceFormat::Text   => text::parse,
+            TraceFormat::Dinero => dinero::parse,
+            TraceFormat::Lackey => lackey::parse,
+        }
+    }
+}
+
+/// Records of the trace at `path`, decoded as `format`; `-` reads stdin.
+/// gzip and zstd input is decompressed on the fly.
+pub fn open(path: &Path, format: TraceFormat) -> io::Result<Records> {
+    let raw: Box<dyn Read> = if is_stdin(path) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let (head, input) = peek_head(decompress(raw)?)?;
+
+    /* trace.din.gz is still a .din trace */
+    let name = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz" | "zst") => Path::new(path.file_stem().unwrap_or_default()),
+        _ => path,
+    };
+    let format = match format {
+        TraceFormat::Auto => TraceFormat::detect(name, &head),
+        format => format,
+    };
+    let routing = format.routing(&head);
+    Ok(match format {
+        TraceFormat::Binary => Box::new(binary::BinaryReader::new(input)?),
+        format              => Box::new(TraceReader { routing, ..TraceReader::new(input, format) }),
+    })
+}
+
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+const HEAD_BYTES: usize = 4 << 10;   // what format and routing detection look at
+
+/* the first HEAD_BYTES of input, fewer at EOF, chained back on; one
+ * fill_buf may hand back only the few bytes decompress peeked */
+fn peek_head(mut input: Box<dyn BufRead>) -> io::Result<(Vec<u8>, Box<dyn BufRead>)> {
+    let mut head = Vec::with_capacity(HEAD_BYTES);
+    (&mut input).take(HEAD_BYTES as u64).read_to_end(&mut head)?;
+    Ok((head.clone(), Box::new(io::Cursor::new(head).chain(input))))
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/* compression is told by its magic whatever the file is called; the magic
+ * is read up front and chained back on, so a pipe works as well as a file */
+fn decompress(mut raw: Box<dyn Read>) -> io::Result<Box<dyn BufRead>> {
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    (&mut raw).take(ZSTD_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    let gzip = magic.starts_with(&GZIP_MAGIC);
+    let zstd = magic.starts_with(&ZSTD_MAGIC);
+    let raw = BufReader::new(io::Cursor::new(magic).chain(raw));
+
+    Ok(if gzip {
+        Box::new(BufReader::new(MultiGzDecoder::new(raw)))
+    } else if zstd {
+        Box::new(BufReader::new(zstd::Decoder::with_buffer(raw)?))
+    } else {
+        Box::new(raw)
+    })
+}
+
+/* ── line-oriented reader ────────────────────────────────────────────── */
+
+/* decode one line into zero or more records; Err(reason) for a bad line */
+type ParseFn = fn(&str, &mut VecDeque<TraceRecord>) -> Result<(), String>;
+
+/// Streams the records of a line-based trace; a bad line yields its
+/// `TraceError` and reading carries on after it.
+pub struct TraceReader<R> {
+    lines: io::Lines<R>,
+    line_no: usize,
+    parse: ParseFn,
+    pending: VecDeque<TraceRecord>,   // records of the last line not yet handed out
+    routing: Routing,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(input: R, format: TraceFormat) -> Self {
+        Self {
+            lines: input.lines(),
+            line_no: 0,
+            parse: format.parser(),
+            pending: VecDeque::new(),
+            routing: format.routing(&[]),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+
+            let line = self.lines.next()?;
+            self.line_no += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e)   => return Some(Err(TraceError::io(self.pos(), e))),
+            };
+
+            if let Err(reason) = (self.parse)(&line, &mut self.pending) {
+                return Some(Err(TraceError::parse(self.pos(), reason)));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Trace for TraceReader<R> {
+    fn pos(&self) -> Position {
+        Position::Line(self.line_no)
+    }
+
+    fn routing(&self) -> Routing {
+        self.routing
+    }
+}
+
+/* ── field parsing shared by the readers ─────────────────────────────── */
+
+fn parse_hex(field: &str) -> Result<usize, String> {
+    let digits = field.strip_prefix("0x").or_else(|| field.strip_prefix("0X")).unwrap_or(field);
+    usize::from_str_radix(digits, 16).map_err(|_| format!("bad hex address '{field}'"))
+}
+
+pub(crate) fn size_of(bytes: usize) -> Result<DataTypeSize, String> {
+    match bytes {
+        1 => Ok(DataTypeSize::Byte),
+        2 => Ok(DataTypeSize::Halfword),
+        4 => Ok(DataTypeSize::Word),
+        8 => Ok(DataTypeSize::DoubleWord),
+        _ => Err(format!("unsupported size {bytes}")),
+    }
+}
+
+/* a store of `bytes` bytes carrying the low bits of raw */
+pub(crate) fn store_of(bytes: usize, raw: u64) -> Result<DataType, String> {
+    Ok(match size_of(bytes)? {
+        DataTypeSize::Byte       => DataType::Byte(raw as u8),
+        DataTypeSize::Halfword   => DataType::Halfword(raw as u16),
+        DataTypeSize::Word       => DataType::Word(raw as u32),
+        DataTypeSize::DoubleWord => DataType::DoubleWord(raw),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(format: TraceFormat, input: &str) -> Vec<Result<TraceRecord, String>> {
+        TraceReader::new(input.as_bytes(), format).map(|r| r.map_err(|e| e.to_string())).collect()
+    }
+
+    #[test]
+    fn detect_by_extension_then_content() {
+        assert_eq!(TraceFormat::detect(Path::new("a.din"), b"r 10 4"), TraceFormat::Dinero);
+        assert_eq!(TraceFormat::detect(Path::new("a.din"), b"CSTR\x01"), TraceFormat::Binary);
+        assert_eq!(TraceFormat::detect(Path::new("a.txt"), b"\n0 1000\n"), TraceFormat::Dinero);
+        assert_eq!(TraceFormat::detect(Path::new("a.txt"), b"r 404 4\n"), TraceFormat::Text);
+        assert_eq!(TraceFormat::detect(Path::new("a.out"), b"==7== Lackey\nI  0400d7d4,8\n"), TraceFormat::Lackey);
+    }
+
+    #[test]
+    fn only_r_w_text_traces_are_routed_by_address() {
+        assert_eq!(TraceFormat::Text.routing(b"r 400000 4\nw 600000 4 1\n"), Routing::Address);
+        assert_eq!(TraceFormat::Text.routing(b"r 600000 4\ni 400000 4\n"), Routing::Kind);
+        assert_eq!(TraceFormat::Lackey.routing(b"I  0400d7d4,8\n"), Routing::Kind);
+        assert_eq!(TraceFormat::Dinero.routing(b"0 1000\n"), Routing::Kind);
+    }
+
+    #[test]
+    fn compressed_input_is_told_by_its_magic() {
+        let text = b"r 404 4\nw 408 4 1\n";
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        io::Write::write_all(&mut gz, text).unwrap();
+        let gz = gz.finish().unwrap();
+        let zst = zstd::encode_all(&text[..], 0).unwrap();
+
+        for raw in [text.to_vec(), gz, zst] {
+            let mut out = Vec::new();
+            decompress(Box::new(io::Cursor::new(raw))).unwrap().read_to_end(&mut out).unwrap();
+            assert_eq!(out, text);
+        }
+    }
+
+    #[test]
+    fn open_sniffs_past_the_compression_magic() {
+        let lackey = b"==1== Lackey, an example Valgrind tool\nI  0400d7d4,4\n L 7ff000398,8\n M 0421d020,2\n";
+        let late_fetch = b"r 600000 4\nw 600004 4 1\ni 400000 4\n";
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        io::Write::write_all(&mut gz, lackey).unwrap();
+        let cases = [
+            ("plain.lackey", lackey.to_vec(), TraceOp::Fetch(DataTypeSize::Word), 4),
+            ("gzipped.lackey", gz.finish().unwrap(), TraceOp::Fetch(DataTypeSize::Word), 4),
+            ("zstd.lackey", zstd::encode_all(&lackey[..], 0).unwrap(), TraceOp::Fetch(DataTypeSize::Word), 4),
+            ("late_fetch.txt", late_fetch.to_vec(), TraceOp::Read(DataTypeSize::Word), 3),
+        ];
+
+        for (name, bytes, first, n) in cases {
+            let path = std::env::temp_dir().join(format!("cache_sim_{}_{name}", std::process::id()));
+            std::fs::write(&path, bytes).unwrap();
+            let records = open(&path, TraceFormat::Auto).unwrap();
+            assert_eq!(records.routing(), Routing::Kind, "{name}");
+            let records: Vec<_> = records.collect::<Result<_, _>>().unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(records.len(), n, "{name}");
+            assert_eq!(records[0].op, first, "{name}");
+        }
+    }
+
+    #[test]
+    fn bad_lines_are_numbered_and_skipped() {
+        let records = read_all(TraceFormat::Text, "r 404 4\n\nq 408 4\nw 40c 4 7\n");
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], Err("line 3: unknown op 'q'".to_string()));
+        assert_eq!(records[2], Ok(TraceRecord::new(0x40c, TraceOp::Write(DataType::Word(7)))));
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::VecDeque;
+
+use super::{parse_hex, size_of, store_of, TraceOp, TraceRecord};
+
+/* one `r|w|i <hex addr> <size> [value]` record, i an instruction fetch;
+ * nothing for a blank line */
+pub(super) fn parse(line: &str, out: &mut VecDeque<TraceRecord>) -> Result<(), String> {
+    let cols: Vec<&str> = line.split_ascii_whitespace().collect();
+    let Some(&op) = cols.first() else { return Ok(()) };
+    if cols.len() < 3 {
+        return Err(format!("expected `{op} <addr> <size>`"));
+    }
+
+    let addr = parse_hex(cols[1])?;
+    let bytes = cols[2].parse::<usize>()
+        .map_err(|_| format!("size must be decimal (1/2/4/8), got '{}'", cols[2]))?;
+
+    let op = match op {
+        "r" => TraceOp::Read(size_of(bytes)?),
+        "i" => TraceOp::Fetch(size_of(bytes)?),
+        "w" => {
+            let Some(value) = cols.get(3) else {
+                return Err("write line needs a value".into());
+            };
+            let raw = value.parse::<u64>()
+                .map_err(|_| format!("value must be decimal, got '{value}'"))?;
+            TraceOp::Write(store_of(bytes, raw)?)
+        }
+        _ => return Err(format!("unknown op '{op}'")),
+    };
+    out.push_back(TraceRecord::new(addr, op));
+    Ok(())
+}
@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::main_memory::MainMemory;
+
+/* ---------------- coalescing write buffer ---------------- */
+/* a FIFO of line-sized entries between the last cache level and main
+ * memory; every store or writeback headed for memory lands here first, a
+ * second write to a buffered line merges into its entry, and entries
+ * retire to memory oldest first. Each entry carries a byte mask so a
+ * partial line never overwrites bytes it does not own */
+
+/// When buffered entries retire to main memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrainPolicy {
+    /// only when a new line needs a slot in a full buffer (that store stalls)
+    #[default]
+    Lazy,
+    /// one entry per access while more than `high_water` entries are held
+    Eager,
+}
+
+impl FromStr for DrainPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lazy"  => Ok(DrainPolicy::Lazy),
+            "eager" => Ok(DrainPolicy::Eager),
+            _ => Err(format!("unknown drain policy '{s}' (expected lazy or eager)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WriteBufferConfig {
+    pub entries: usize,         // lines held before a store stalls
+    #[serde(default)]
+    pub drain: DrainPolicy,
+    #[serde(default)]
+    pub high_water: usize,      // eager only: occupancy left undrained
+}
+
+impl WriteBufferConfig {
+    pub fn new(entries: usize) -> Self {
+        Self { entries, drain: DrainPolicy::default(), high_water: 0 }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WriteBufferStats {
+    writes: usize,          // stores and writebacks received
+    merges: usize,          // ... that landed in an entry already held
+    stalls: usize,          // ... that found the buffer full
+    drains: usize,          // entries retired to memory
+    forwards: usize,        // line fetches that picked up buffered bytes
+    occupancy_sum: usize,   // entries held, summed over every access
+    samples: usize,
+    max_occupancy: usize,
+}
+
+impl WriteBufferStats {
+    pub fn writes(&self) -> usize { self.writes }
+    pub fn merges(&self) -> usize { self.merges }
+    pub fn stalls(&self) -> usize { self.stalls }
+    pub fn drains(&self) -> usize { self.drains }
+    pub fn forwards(&self) -> usize { self.forwards }
+    pub fn max_occupancy(&self) -> usize { self.max_occupancy }
+
+    pub fn avg_occupancy(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.occupancy_sum as f64 / self.samples as f64
+        }
+    }
+
+    pub fn merge_rate(&self) -> f64 {
+        if self.writes == 0 {
+            0.0
+        } else {
+            self.merges as f64 / self.writes as f64
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    base: usize,
+    data: Vec<u8>,
+    mask: Vec<bool>,        // bytes of data that hold buffered writes
+}
+
+#[derive(Debug)]
+pub struct WriteBuffer {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    line_bytes: usize,
+    drain: DrainPolicy,
+    high_water: usize,
+    stats: WriteBufferStats,
+}
+
+impl WriteBuffer {
+    pub fn new(config: &WriteBufferConfig, line_bytes: usize) -> Self {
+        assert!(line_bytes.is_power_of_two(), "write buffer lines must be a power of two");
+        Self {
+            entries: VecDeque::with_capacity(config.entries),
+            capacity: config.entries,
+            line_bytes,
+            drain: config.drain,
+            high_water: config.high_water,
+            stats: WriteBufferStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &WriteBufferStats {
+        &self.stats
+    }
+
+    pub fn occupancy(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline(always)]
+    fn base(&self, addr: usize) -> usize {
+        addr & !(self.line_bytes - 1)
+    }
+
+    /* buffer bytes at addr, merging with a held entry or taking a new slot
+     * (retiring the oldest first when full); bytes never cross a line */
+    pub fn write(&mut self, addr: usize, bytes: &[u8], main: &mut MainMemory) {
+        let base = self.base(addr);
+        let offset = addr - base;
+        assert!(offset + bytes.len() <= self.line_bytes, "write crosses a write buffer line");
+        self.stats.writes += 1;
+
+        let idx = match self.entries.iter().position(|e| e.base == base) {
+            Some(idx) => {
+                self.stats.merges += 1;
+                idx
+            }
+            None => {
+                if self.entries.len() == self.capacity {
+                    self.stats.stalls += 1;
+                    self.retire(main);
+                }
+                self.entries.push_back(Entry {
+                    base,
+                    data: vec![0; self.line_bytes],
+                    mask: vec![false; self.line_bytes],
+                });
+                self.entries.len() - 1
+            }
+        };
+
+        let entry = &mut self.entries[idx];
+        entry.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        entry.mask[offset..offset + bytes.len()].fill(true);
+        self.stats.max_occupancy = self.stats.max_occupancy.max(self.entries.len());
+    }
+
+    /* overlay buffered bytes on `line`, freshly fetched from memory at addr */
+    pub fn forward(&mut self, addr: usize, line: &mut [u8]) {
+        let base = self.base(addr);
+        let Some(entry) = self.entries.iter().find(|e| e.base == base) else { return };
+
+        let offset = addr - base;
+        let mut hit = false;
+        for (i, byte) in line.iter_mut().enumerate() {
+            if entry.mask[offset + i] {
+                *byte = entry.data[offset + i];
+                hit = true;
+            }
+        }
+        if hit {
+            self.stats.forwards += 1;
+        }
+    }
+
+    /* one access has gone by: sample occupancy, then drain per policy */
+    pub fn tick(&mut self, main: &mut MainMemory) {
+        self.stats.occupancy_sum += self.entries.len();
+        self.stats.samples += 1;
+
+        if self.drain == DrainPolicy::Eager && self.entries.len() > self.high_water {
+            self.retire(main);
+        }
+    }
+
+    /* retire everything still held, e.g. at the end of a run */
+    pub fn flush(&mut self, main: &mut MainMemory) {
+        while !self.entries.is_empty() {
+            self.retire(main);
+        }
+    }
+
+    fn retire(&mut self, main: &mut MainMemory) {
+        if let Some(entry) = self.entries.pop_front() {
+            main.write_masked(entry.base, &entry.data, &entry.mask);
+            self.stats.drains += 1;
+        }
+    }
+
+    pub fn print_summary(&self) {
+        let s = &self.stats;
+        println!("\tEntries: {} x {} bytes, {:?} drain", self.capacity, self.line_bytes, self.drain);
+        println!("\tWrites In: {} ({} merged, {:.2}%)", s.writes, s.merges, s.merge_rate() * 100.0);
+        println!("\tStalls: {}", s.stalls);
+        println!("\tDrained: {} ({} still held)", s.drains, self.entries.len());
+        println!("\tForwarded Fetches: {}", s.forwards);
+        println!("\tOccupancy: {:.2} avg, {} max", s.avg_occupancy(), s.max_occupancy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemLevelAccess;
+
+    fn buffer(entries: usize, drain: DrainPolicy) -> WriteBuffer {
+        WriteBuffer::new(&WriteBufferConfig { entries, drain, high_water: 0 }, 16)
+    }
+
+    #[test]
+    fn stores_to_one_line_merge() {
+        let mut main = MainMemory::new(1 << 8);
+        let mut wb = buffer(2, DrainPolicy::Lazy);
+
+        for word in 0..4usize {
+            wb.write(0x20 + 4 * word, &(word as u32).to_le_bytes(), &mut main);
+        }
+        assert_eq!(wb.occupancy(), 1);
+        assert_eq!(wb.stats().merges(), 3);
+
+        /* nothing reached memory yet, but a fetch sees the stores */
+        let mut line = main.fetch_line(0x20, 4, false).unwrap();
+        assert_eq!(line[4], 0);
+        wb.forward(0x20, &mut line);
+        assert_eq!(&line[4..8], &1u32.to_le_bytes());
+        assert_eq!(wb.stats().forwards(), 1);
+    }
+
+    #[test]
+    fn full_buffer_stalls_and_retires_oldest() {
+        let mut main = MainMemory::new(1 << 8);
+        let mut wb = buffer(2, DrainPolicy::Lazy);
+
+        wb.write(0x00, &[1], &mut main);
+        wb.write(0x10, &[2], &mut main);
+        wb.write(0x21, &[3], &mut main);
+        assert_eq!(wb.stats().stalls(), 1);
+        assert_eq!(wb.stats().drains(), 1);
+        assert_eq!(main.fetch_line(0x00, 4, false).unwrap()[0], 1);
+
+        /* the mask keeps a partial line from clobbering its neighbours */
+        main.write_line(0x20, 4, vec![9; 16]);
+        wb.flush(&mut main);
+        let line = main.fetch_line(0x20, 4, false).unwrap();
+        assert_eq!(&line[..3], &[9, 3, 9]);
+        assert_eq!(wb.occupancy(), 0);
+    }
+
+    #[test]
+    fn eager_drains_down_to_high_water() {
+        let mut main = MainMemory::new(1 << 8);
+        let mut wb = WriteBuffer::new(
+            &WriteBufferConfig { entries: 4, drain: DrainPolicy::Eager, high_water: 1 }, 16,
+        );
+
+        for line in 0..3 {
+            wb.write(line * 0x10, &[1], &mut main);
+        }
+        for _ in 0..4 {
+            wb.tick(&mut main);
+        }
+        assert_eq!(wb.occupancy(), 1);
+        assert_eq!(wb.stats().drains(), 2);
+        assert_eq!(wb.stats().stalls(), 0);
+        assert_eq!(wb.stats().max_occupancy(), 3);
+    }
+}